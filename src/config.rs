@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use failure::Error;
+
+/// Settings read from the config file.
+///
+/// The file uses a small subset of TOML: `key = value` lines, optionally grouped under
+/// `[section]` headers, and `#` comments. Keys are named after the long command line options
+/// (`_` may be used in place of `-`), keys before the first header apply globally. A section
+/// named after a subcommand, e.g. `[focus-workspace]`, holds defaults for that subcommand's
+/// options which win over the global keys; flags given on the command line win over both.
+#[derive(Default)]
+pub struct Config {
+    sections: HashMap<String, HashMap<String, String>>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, Error> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format_err!("Can't read config {}: {}", path.display(), e))?;
+        let mut config = Config::default();
+        let mut section = String::new();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name.trim().to_string();
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| {
+                format_err!("{}:{}: expected `key = value`", path.display(), number + 1)
+            })?;
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            config
+                .sections
+                .entry(section.clone())
+                .or_default()
+                .insert(key.trim().replace('_', "-"), value.to_string());
+        }
+        Ok(config)
+    }
+
+    /// Looks up `key` in `section`, the global keys live in the section named `""`.
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.sections.get(section)?.get(key).map(|v| v.as_str())
+    }
+}
@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use failure::Error;
+
+/// The last selection of each subcommand, kept in `$XDG_STATE_HOME/niri-action/history` as
+/// `subcommand<TAB>key` lines. The key is the id or name of the chosen entry.
+pub struct History {
+    path: PathBuf,
+    subcommand: String,
+    last: HashMap<String, String>,
+}
+
+impl History {
+    /// Reads the history for `subcommand`, a missing file is an empty history.
+    pub fn load(subcommand: &str) -> History {
+        let path = state_dir().join("niri-action").join("history");
+        let last = std::fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .map(|(subcommand, key)| (subcommand.to_string(), key.to_string()))
+            .collect();
+        History { path, subcommand: subcommand.to_string(), last }
+    }
+
+    /// Key chosen the last time the subcommand ran.
+    pub fn last(&self) -> Option<&str> {
+        self.last.get(&self.subcommand).map(|key| key.as_str())
+    }
+
+    pub fn save(mut self, key: &str) -> Result<(), Error> {
+        self.last.insert(self.subcommand.clone(), key.replace(['\t', '\n'], " "));
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut content: Vec<String> = self.last.iter().map(|(subcommand, key)| format!("{subcommand}\t{key}\n")).collect();
+        content.sort();
+        std::fs::write(&self.path, content.concat())
+            .map_err(|e| format_err!("Can't write history {}: {}", self.path.display(), e))
+    }
+}
+
+fn state_dir() -> PathBuf {
+    std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .unwrap_or_else(std::env::temp_dir)
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use failure::Error;
+
+/// Resolves window app ids to icon names for pickers which can show icons.
+///
+/// The optional map file is consulted first, then the `Icon=` key of the `<app_id>.desktop`
+/// file in the XDG application directories. If neither knows the app id, the app id itself is
+/// used as the icon name, which is right for most applications.
+#[derive(Default)]
+pub struct Icons {
+    overrides: HashMap<String, String>,
+}
+
+impl Icons {
+    /// Reads `app_id = icon_name` lines from `path`, `#` starts a comment.
+    pub fn load_map(path: &Path) -> Result<Icons, Error> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format_err!("Can't read icon map {}: {}", path.display(), e))?;
+        let mut overrides = HashMap::new();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (app_id, icon) = line.split_once('=').ok_or_else(|| {
+                format_err!("{}:{}: expected `app_id = icon_name`", path.display(), number + 1)
+            })?;
+            overrides.insert(app_id.trim().to_string(), icon.trim().to_string());
+        }
+        Ok(Icons { overrides })
+    }
+
+    pub fn lookup(&self, app_id: &str) -> String {
+        if let Some(icon) = self.overrides.get(app_id) {
+            return icon.clone();
+        }
+        desktop_file_icon(app_id).unwrap_or_else(|| app_id.to_string())
+    }
+}
+
+/// The icon theme configured for GTK applications, read with gsettings if it is installed.
+pub fn default_theme() -> Option<String> {
+    if !crate::picker::on_path("gsettings") {
+        return None;
+    }
+    let output = std::process::Command::new("gsettings")
+        .args(["get", "org.gnome.desktop.interface", "icon-theme"])
+        .output()
+        .ok()?;
+    let theme = String::from_utf8(output.stdout).ok()?;
+    let theme = theme.trim().trim_matches('\'');
+    match output.status.success() && !theme.is_empty() {
+        true => Some(theme.to_string()),
+        false => None,
+    }
+}
+
+/// Reads the `Icon=` key of the desktop file named after `app_id`.
+fn desktop_file_icon(app_id: &str) -> Option<String> {
+    let names = [format!("{app_id}.desktop"), format!("{}.desktop", app_id.to_lowercase())];
+    application_dirs()
+        .iter()
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .find_map(|path| std::fs::read_to_string(path).ok())
+        .and_then(|content| {
+            content
+                .lines()
+                .find_map(|line| line.strip_prefix("Icon="))
+                .map(|icon| icon.trim().to_string())
+        })
+}
+
+/// `$XDG_DATA_HOME/applications` followed by the `applications` directory of each
+/// `$XDG_DATA_DIRS` entry.
+fn application_dirs() -> Vec<PathBuf> {
+    let home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")));
+    let dirs = std::env::var("XDG_DATA_DIRS").unwrap_or("/usr/local/share:/usr/share".to_string());
+    home.into_iter()
+        .chain(dirs.split(':').filter(|dir| !dir.is_empty()).map(PathBuf::from))
+        .map(|dir| dir.join("applications"))
+        .collect()
+}
@@ -1,6 +1,10 @@
-use std::io::Write;
-use std::process::{Command, Stdio};
-use std::collections::HashMap;
+// failure_derive emits its impls inside an anonymous const
+#![allow(non_local_definitions)]
+
+use std::io::{BufRead, Write};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 extern crate clap;
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
@@ -17,24 +21,161 @@ use std::path::Path;
 
 use niri_ipc::{Action, Request, Response };
 
+mod config;
+use config::Config;
+
+mod history;
+use history::History;
+
+mod icons;
+use icons::Icons;
+
+mod picker;
+use picker::Picker;
+
+/// Version reported by `--version` and the version subcommand.
+const VERSION: &str = "v0.1.7";
+/// Version of the niri-ipc crate this is built against, keep in sync with Cargo.toml.
+const NIRI_IPC_VERSION: &str = "25.11";
+
 #[derive(Debug, Fail)]
 enum NiriIPCError {
     #[fail(display = "Not handled: {}", err)]
     UnhandledError { err: String },
+    #[fail(display = "Picker {} is not available", picker)]
+    PickerNotFound { picker: String },
+    #[fail(display = "Selection cancelled")]
+    PickerCancelled,
+    #[fail(display = "No workspace is focused")]
+    NoFocusedWorkspace,
+    #[fail(display = "could not parse {} from selection '{}'", what, selection)]
+    ParseId { what: String, selection: String },
+    #[fail(display = "no matching {} for '{}'", what, input)]
+    NoMatch { what: String, input: String },
+    #[fail(display = "Can't connect to niri: {}", err)]
+    SocketConnect { err: String },
+    #[fail(display = "niri refused the action: {}", err)]
+    ActionFailed { err: String },
+    #[fail(display = "Window {} no longer exists", id)]
+    WindowGone { id: u64 },
+}
+
+impl NiriIPCError {
+    /// Exit status for the error: 1 if nothing was selected (like dmenu), 3 if niri could not be
+    /// reached, 4 if the chosen window closed before acting on it, 127 if the picker is missing
+    /// and 2 for everything else.
+    fn exit_code(&self) -> i32 {
+        match self {
+            NiriIPCError::PickerCancelled => 1,
+            NiriIPCError::SocketConnect { .. } => 3,
+            NiriIPCError::WindowGone { .. } => 4,
+            NiriIPCError::PickerNotFound { .. } => 127,
+            _ => 2,
+        }
+    }
+
+    /// Name of the variant, the `kind` of `--json` errors.
+    fn kind(&self) -> &'static str {
+        match self {
+            NiriIPCError::UnhandledError { .. } => "UnhandledError",
+            NiriIPCError::PickerNotFound { .. } => "PickerNotFound",
+            NiriIPCError::PickerCancelled => "PickerCancelled",
+            NiriIPCError::NoFocusedWorkspace => "NoFocusedWorkspace",
+            NiriIPCError::ParseId { .. } => "ParseId",
+            NiriIPCError::NoMatch { .. } => "NoMatch",
+            NiriIPCError::SocketConnect { .. } => "SocketConnect",
+            NiriIPCError::ActionFailed { .. } => "ActionFailed",
+            NiriIPCError::WindowGone { .. } => "WindowGone",
+        }
+    }
+}
+
+/// How often `--retry-on-failure` shows the picker again.
+const MAX_ACTION_RETRIES: usize = 3;
+
+/// Set by `--quiet`, silences warnings and informational messages.
+static QUIET: AtomicBool = AtomicBool::new(false);
+/// Set by `--strict`, turns unexpected niri responses into errors.
+static STRICT: AtomicBool = AtomicBool::new(false);
+/// Set by `--json`, errors are printed as JSON objects.
+static JSON: AtomicBool = AtomicBool::new(false);
+/// Set by `--print-request`, requests are printed to stderr as they are sent.
+static PRINT_REQUEST: AtomicBool = AtomicBool::new(false);
+/// Set by `-v`, enables debug messages.
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// Prints a warning to stderr unless `--quiet` was given.
+fn warn(message: &str) {
+    if !QUIET.load(Ordering::Relaxed) {
+        eprintln!("Warning: {message}");
+    }
+}
+
+/// Prints an informational message to stderr unless `--quiet` was given, stdout is kept for the
+/// requested output.
+fn info(message: &str) {
+    if !QUIET.load(Ordering::Relaxed) {
+        eprintln!("{message}");
+    }
+}
+
+/// Prints a debug message to stderr if `-v` was given.
+fn debug(message: &str) {
+    if VERBOSE.load(Ordering::Relaxed) {
+        eprintln!("{message}");
+    }
 }
 
 struct ApplicationState<'a> {
-    socket: &'a mut niri_ipc::socket::Socket,
+    socket: &'a mut dyn QueryRun,
     confdir: &'a Path,
+    picker: Box<dyn Picker>,
+    prompt: Option<String>,
+    prompt_count: bool,
+    /// Placeholder text of free-text prompts.
+    placeholder: Option<String>,
+    /// Treat confirming an empty free-text prompt as cancelling it.
+    exit_on_empty: bool,
+    /// Take the only entry of a list without showing the picker.
+    no_picker_on_single: bool,
+    /// Take this entry of every list without showing the picker, counting from 1.
+    select_index: Option<usize>,
+    /// Carry on after a failed action of a subcommand sending several.
+    keep_going: bool,
+    labels: LabelOptions,
+    config: Config,
+    /// Key of the entry acted on (or the typed line), for `--print-selection`.
+    selected: RefCell<Option<String>>,
+    /// Set by `--history`, the last choice is listed first and replaced by the new one.
+    history: Option<History>,
 }
 
-trait QueryRun {
-    fn query(&mut self, request: niri_ipc::Request) -> Result<Option<niri_ipc::Response>, Error>;
-    fn run_action(&mut self, request: niri_ipc::Request) -> Result<(), Error>;
+/// Controls how entries are rendered for the picker.
+struct LabelOptions {
+    max_title_len: Option<usize>,
+    /// Append the process id to window labels.
+    include_pid: bool,
+    /// Append the app id to window labels.
+    include_app_id: bool,
+    /// Shorten the app ids shown in labels to their last dot separated part.
+    trim_app_id: bool,
+    /// Append the index of their workspace to window labels.
+    workspace_index: bool,
+    /// Set if the picker shows icons, windows then carry the icon of their app id.
+    icons: Option<Icons>,
+    /// Keep newlines in window titles, for pickers reading NUL separated entries.
+    keep_newlines: bool,
+    /// Stands in for a missing window title, workspace name or output serial.
+    missing: String,
 }
 
-impl QueryRun for niri_ipc::socket::Socket {
+/// Connection to niri, implemented by the socket and by the scripted fake of the tests.
+trait QueryRun {
+    /// Sends `request` and returns niri's reply as is.
+    fn send(&mut self, request: Request) -> Result<niri_ipc::Reply, Error>;
+
     fn query(&mut self, request: niri_ipc::Request) -> Result<Option<niri_ipc::Response>, Error> {
+        print_request(&request);
         match self.send(request)? {
             Ok(niri_ipc::Response::Handled) => Ok(None),
             Ok(x) => Ok(Some(x)),
@@ -42,110 +183,1446 @@ impl QueryRun for niri_ipc::socket::Socket {
         }
     }
 
+    /// Sends a request which is not expected to return data. Actions answer with `Handled`,
+    /// output configuration requests with `OutputConfigChanged`, where a missing output is an
+    /// error. Any other response is an error.
     fn run_action(&mut self, request: niri_ipc::Request) -> Result<(), Error> {
+        let output = match &request {
+            Request::Output { output, .. } => Some(output.clone()),
+            _ => None,
+        };
+        let window = match &request {
+            Request::Action(Action::FocusWindow { id }) => Some(*id),
+            Request::Action(Action::CloseWindow { id }) => *id,
+            Request::Action(Action::MoveWindowToWorkspace { window_id, .. }) => *window_id,
+            _ => None,
+        };
+        print_request(&request);
         match self.send(request)? {
             Ok(niri_ipc::Response::Handled) => Ok(()),
+            Ok(niri_ipc::Response::OutputConfigChanged(niri_ipc::OutputConfigChanged::Applied)) => Ok(()),
+            Ok(niri_ipc::Response::OutputConfigChanged(niri_ipc::OutputConfigChanged::OutputWasMissing)) => {
+                Err(format_err!("Output {} is not connected", output.unwrap_or_default()))
+            }
             Ok(x) => Err(NiriIPCError::UnhandledError { err: format!("Got result for {:?}", x).to_string() })?,
-            Err(err) => Err(NiriIPCError::UnhandledError { err })?,
+            Err(err) => {
+                // the window may have closed since it was listed
+                if let Some(id) = window
+                    && let Some(Response::Windows(windows)) = self.query(Request::Windows)?
+                    && !windows.iter().any(|x| x.id == id)
+                {
+                    Err(NiriIPCError::WindowGone { id })?
+                }
+                Err(NiriIPCError::ActionFailed { err })?
+            }
+        }
+    }
+}
+
+impl QueryRun for niri_ipc::socket::Socket {
+    fn send(&mut self, request: Request) -> Result<niri_ipc::Reply, Error> {
+        Ok(niri_ipc::socket::Socket::send(self, request)?)
+    }
+}
+
+/// Prints `request` in its wire format if `--print-request` was given, even with `--quiet`.
+fn print_request(request: &Request) {
+    if PRINT_REQUEST.load(Ordering::Relaxed) {
+        match serde_json::to_string(request) {
+            Ok(json) => eprintln!("{json}"),
+            Err(err) => warn(&format!("Can't serialize {request:?}: {err}")),
+        }
+    }
+}
+
+/// Subcommand that sends a single argument-free action without a picker.
+struct DirectAction {
+    name: &'static str,
+    about: &'static str,
+    action: fn() -> Action,
+}
+
+const DIRECT_ACTIONS: &[DirectAction] = &[
+    DirectAction { name: "focus-monitor-left", about: "Focus the monitor to the left", action: || Action::FocusMonitorLeft {} },
+    DirectAction { name: "focus-monitor-right", about: "Focus the monitor to the right", action: || Action::FocusMonitorRight {} },
+    DirectAction { name: "focus-monitor-up", about: "Focus the monitor above", action: || Action::FocusMonitorUp {} },
+    DirectAction { name: "focus-monitor-down", about: "Focus the monitor below", action: || Action::FocusMonitorDown {} },
+    DirectAction { name: "move-window-to-workspace-up", about: "Move the focused window to the workspace above", action: || Action::MoveWindowToWorkspaceUp { focus: true } },
+    DirectAction { name: "move-window-to-workspace-down", about: "Move the focused window to the workspace below", action: || Action::MoveWindowToWorkspaceDown { focus: true } },
+    DirectAction { name: "focus-workspace-up", about: "Focus the workspace above", action: || Action::FocusWorkspaceUp {} },
+    DirectAction { name: "focus-workspace-down", about: "Focus the workspace below", action: || Action::FocusWorkspaceDown {} },
+    DirectAction { name: "focus-column-left", about: "Focus the column to the left", action: || Action::FocusColumnLeft {} },
+    DirectAction { name: "focus-column-right", about: "Focus the column to the right", action: || Action::FocusColumnRight {} },
+    DirectAction { name: "move-column-left", about: "Move the focused column to the left", action: || Action::MoveColumnLeft {} },
+    DirectAction { name: "move-column-right", about: "Move the focused column to the right", action: || Action::MoveColumnRight {} },
+    DirectAction { name: "move-column-to-workspace-up", about: "Move the focused column to the workspace above", action: || Action::MoveColumnToWorkspaceUp { focus: true } },
+    DirectAction { name: "move-column-to-workspace-down", about: "Move the focused column to the workspace below", action: || Action::MoveColumnToWorkspaceDown { focus: true } },
+    DirectAction { name: "focus-floating", about: "Focus the floating layout", action: || Action::FocusFloating {} },
+    DirectAction { name: "focus-tiling", about: "Focus the tiling layout", action: || Action::FocusTiling {} },
+    DirectAction { name: "switch-focus-between-floating-and-tiling", about: "Move the focus between the floating and the tiling layout", action: || Action::SwitchFocusBetweenFloatingAndTiling {} },
+    DirectAction { name: "toggle-column-tabbed-display", about: "Toggle the focused column between normal and tabbed display", action: || Action::ToggleColumnTabbedDisplay {} },
+];
+
+/// Subcommand run by an `ApplicationState` method, with the niri actions it may send.
+struct Handler {
+    name: &'static str,
+    run: fn(&mut ApplicationState, &ArgMatches) -> Result<(), Error>,
+    /// Variants of `Action`, listed by `debug actions`.
+    actions: &'static [&'static str],
+}
+
+/// The subcommands `dispatch` runs besides `DIRECT_ACTIONS`.
+const HANDLERS: &[Handler] = &[
+    Handler { name: "focus-container", run: |s, m| s.focus_container_by_id(m), actions: &["FocusWindow", "Spawn", "SpawnSh"] },
+    Handler { name: "urgent", run: |s, _| s.focus_urgent(), actions: &["FocusWindow"] },
+    Handler { name: "steal-container", run: |s, m| s.steal_container_by_id(m), actions: &["MoveWindowToWorkspace"] },
+    Handler { name: "close-window", run: |s, m| s.close_window(m), actions: &["CloseWindow"] },
+    Handler { name: "focus-workspace", run: |s, m| s.focus_workspace_by_name(m), actions: &["FocusWorkspace", "SetWorkspaceName"] },
+    Handler { name: "move-to-workspace", run: |s, m| s.move_to_workspace_by_name(m), actions: &["MoveWindowToWorkspace", "FocusWorkspace", "FocusWindow", "SetWorkspaceName"] },
+    Handler { name: "name-current-workspace", run: |s, _| s.name_current_workspace(), actions: &["SetWorkspaceName", "UnsetWorkspaceName"] },
+    Handler { name: "move-workspace-to-output", run: |s, _| s.move_workspace_to_output(), actions: &["MoveWorkspaceToMonitor"] },
+    Handler { name: "focus-output", run: |s, m| s.focus_output(m), actions: &["FocusMonitor"] },
+    Handler { name: "move-column-to-monitor", run: |s, _| s.move_column_to_monitor(), actions: &["MoveColumnToMonitor"] },
+    // output configuration requests, not actions
+    Handler { name: "set-output-scale", run: |s, _| s.set_output_scale(), actions: &[] },
+    Handler { name: "toggle-output", run: |s, _| s.toggle_output(), actions: &[] },
+    Handler { name: "workspace-exec", run: |s, m| s.workspace_exec(m), actions: &[] },
+    Handler { name: "raw", run: |s, m| s.raw(m), actions: &[] },
+    Handler { name: "version", run: |s, m| s.version(m), actions: &[] },
+    Handler { name: "list", run: |s, m| s.list(m), actions: &[] },
+];
+
+/// Every `(subcommand, action variant)` pair, `HANDLERS` first.
+fn wrapped_actions() -> Vec<(&'static str, String)> {
+    let mut pairs: Vec<(&'static str, String)> = HANDLERS
+        .iter()
+        .flat_map(|h| h.actions.iter().map(move |variant| (h.name, variant.to_string())))
+        .collect();
+    for d in DIRECT_ACTIONS {
+        let action = format!("{:?}", (d.action)());
+        let variant = action.split([' ', '{', '(']).next().unwrap_or(&action).to_string();
+        pairs.push((d.name, variant));
+    }
+    pairs
+}
+
+/// Prints each subcommand with a niri action it sends, one pair per line.
+fn print_actions() {
+    for (name, variant) in wrapped_actions() {
+        println!("{name}\t{variant}");
+    }
+}
+
+fn main() {
+    if let Err(err) = run() {
+        let (code, kind) = match err.downcast_ref::<NiriIPCError>() {
+            Some(err) => (err.exit_code(), err.kind()),
+            None => (2, "Other"),
+        };
+        // scripts get every error, cancelling included
+        if JSON.load(Ordering::Relaxed) {
+            eprintln!("{}", serde_json::json!({ "error": err.to_string(), "kind": kind }));
+        } else if code != 1 {
+            eprintln!("Error: {err}");
         }
+        std::process::exit(code);
     }
 }
 
-fn main() -> Result<(), Error> {
+fn run() -> Result<(), Error> {
     let matches = App::new("niri-action")
-        .version("v0.1.7")
+        .version(VERSION)
         .author("Rouven Czerwinski <rouven@czerwinskis.de>")
         .about("Provides selections of niri $things via fuzzel")
         .setting(AppSettings::ArgRequiredElseHelp)
         .setting(AppSettings::TrailingVarArg)
         .arg(Arg::with_name("confdir").default_value("~/.config/niri-action/"))
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .takes_value(true)
+                .global(true)
+                .help("Config file to use instead of <confdir>/config.toml"),
+        )
+        .arg(
+            Arg::with_name("picker")
+                .long("picker")
+                .takes_value(true)
+                .possible_values(picker::NAMES)
+                .global(true)
+                .help("Menu program used for selections [default: fuzzel]"),
+        )
+        .arg(
+            Arg::with_name("warp-command")
+                .long("warp-command")
+                .takes_value(true)
+                .global(true)
+                .help("Pointer warp helper for --warp, {x} and {y} are replaced [default: ydotool mousemove --absolute -x {x} -y {y}]"),
+        )
+        .arg(
+            Arg::with_name("list-pickers")
+                .long("list-pickers")
+                .help("List the supported pickers and whether they are installed"),
+        )
+        .arg(
+            Arg::with_name("print-socket")
+                .long("print-socket")
+                .help("Print the path of the niri socket that would be used and exit"),
+        )
+        .arg(
+            Arg::with_name("null-input")
+                .long("null-input")
+                .global(true)
+                .help("Pass NUL separated entries so window titles keep their newlines (fzf only)"),
+        )
+        .arg(
+            Arg::with_name("retry-picker")
+                .long("retry-picker")
+                .global(true)
+                .help("Start the picker once more if it dies while printing the selection, instead of cancelling"),
+        )
+        .arg(
+            Arg::with_name("picker-no-config")
+                .long("picker-no-config")
+                .global(true)
+                .help("Ignore the picker's own config file and options from the environment"),
+        )
+        .arg(
+            Arg::with_name("terminal")
+                .long("terminal")
+                .takes_value(true)
+                .global(true)
+                .help("Terminal hosting the fzf picker [default: $TERMINAL]"),
+        )
+        .arg(
+            Arg::with_name("prompt")
+                .long("prompt")
+                .takes_value(true)
+                .global(true)
+                .help("Prompt shown by the picker"),
+        )
+        .arg(
+            Arg::with_name("placeholder")
+                .long("placeholder")
+                .takes_value(true)
+                .global(true)
+                .help("Placeholder shown in free-text prompts, e.g. \"enter new name\" (fuzzel, tofi)"),
+        )
+        .arg(
+            Arg::with_name("exit-on-empty")
+                .long("exit-on-empty")
+                .global(true)
+                .help("Treat an empty free-text input as cancel [default]"),
+        )
+        .arg(
+            Arg::with_name("no-exit-on-empty")
+                .long("no-exit-on-empty")
+                .conflicts_with("exit-on-empty")
+                .global(true)
+                .help("Pass an empty free-text input on, e.g. to unset a workspace name"),
+        )
+        .arg(
+            Arg::with_name("hidden")
+                .long("hidden")
+                .global(true)
+                .help("Hide the text typed into free-text prompts (fuzzel, tofi)"),
+        )
+        .arg(
+            Arg::with_name("prompt-count")
+                .long("prompt-count")
+                .global(true)
+                .help("Show the number of entries in the prompt, e.g. \"window (42)> \""),
+        )
+        .arg(
+            Arg::with_name("no-picker-on-single")
+                .long("no-picker-on-single")
+                .global(true)
+                .help("Choose the only entry of a list without showing the picker"),
+        )
+        .arg(
+            Arg::with_name("select-first")
+                .long("select-first")
+                .global(true)
+                .help("Choose the first entry of a list without showing the picker"),
+        )
+        .arg(
+            Arg::with_name("select-index")
+                .long("select-index")
+                .takes_value(true)
+                .value_name("N")
+                .validator(is_number)
+                .conflicts_with("select-first")
+                .global(true)
+                .help("Choose the Nth entry of a list without showing the picker, counting from 1"),
+        )
+        .arg(
+            Arg::with_name("width")
+                .long("width")
+                .takes_value(true)
+                .validator(is_number)
+                .global(true)
+                .help("Picker width in characters"),
+        )
+        .arg(
+            Arg::with_name("auto-width")
+                .long("auto-width")
+                .conflicts_with("width")
+                .global(true)
+                .help("Make the picker as wide as the longest entry (fuzzel)"),
+        )
+        .arg(
+            Arg::with_name("lines")
+                .long("lines")
+                .takes_value(true)
+                .validator(is_number)
+                .global(true)
+                .help("Number of entries the picker shows at once"),
+        )
+        .arg(
+            Arg::with_name("font")
+                .long("font")
+                .takes_value(true)
+                .global(true)
+                .help("Picker font, e.g. \"monospace:size=12\""),
+        )
+        .arg(
+            Arg::with_name("colors")
+                .long("colors")
+                .takes_value(true)
+                .value_name("key=color,...")
+                .global(true)
+                .help("Picker colors, keys are background, text, prompt, input, match, selection, selection-text, selection-match and border"),
+        )
+        .arg(
+            Arg::with_name("match")
+                .long("match")
+                .takes_value(true)
+                .possible_values(picker::MATCH_MODES)
+                .global(true)
+                .help("How typed text matches the entries, exact matches substrings"),
+        )
+        .arg(
+            Arg::with_name("initial")
+                .long("initial")
+                .takes_value(true)
+                .value_name("text")
+                .global(true)
+                .help("Start with this text in the picker's filter (fuzzel, bemenu, fzf)"),
+        )
+        .arg(
+            Arg::with_name("auto-select")
+                .long("auto-select")
+                .global(true)
+                .help("Confirm as soon as only one entry matches the typed text (fuzzel, tofi), with focus-workspace --index-prefix typing the index of a workspace on the focused output jumps there unless its name contains that number"),
+        )
+        .arg(
+            Arg::with_name("counter")
+                .long("counter")
+                .global(true)
+                .help("Show the number of matching entries while typing, e.g. \"3/42\" (fuzzel, fzf)"),
+        )
+        .arg(
+            Arg::with_name("max-title-len")
+                .long("max-title-len")
+                .takes_value(true)
+                .validator(is_number)
+                .global(true)
+                .help("Truncate window titles longer than this many characters"),
+        )
+        .arg(
+            Arg::with_name("include-index-in-window-label")
+                .long("include-index-in-window-label")
+                .global(true)
+                .help("Append the index of their workspace to window labels, e.g. \"[ws 2]\""),
+        )
+        .arg(
+            Arg::with_name("missing-label")
+                .long("missing-label")
+                .takes_value(true)
+                .global(true)
+                .help("Text shown for missing window titles, workspace names and output serials [default: <unknown>]"),
+        )
+        .arg(
+            Arg::with_name("include-pid")
+                .long("include-pid")
+                .global(true)
+                .help("Append the process id to window labels"),
+        )
+        .arg(
+            Arg::with_name("include-app-id")
+                .long("include-app-id")
+                .global(true)
+                .help("Append the app id to window labels"),
+        )
+        .arg(
+            Arg::with_name("trim-app-id")
+                .long("trim-app-id")
+                .global(true)
+                .help("Show only the last part of reverse-DNS app ids in labels, e.g. \"firefox\" for org.mozilla.firefox"),
+        )
+        .arg(
+            Arg::with_name("icons")
+                .long("icons")
+                .global(true)
+                .help("Show application icons next to windows (fuzzel only)"),
+        )
+        .arg(
+            Arg::with_name("icon-theme")
+                .long("icon-theme")
+                .takes_value(true)
+                .global(true)
+                .help("Icon theme for --icons [default: the GTK icon-theme from gsettings]"),
+        )
+        .arg(
+            Arg::with_name("app-id-icon-map")
+                .long("app-id-icon-map")
+                .takes_value(true)
+                .global(true)
+                .help("File of `app_id = icon_name` lines overriding the icon lookup for --icons"),
+        )
+        .arg(
+            Arg::with_name("history")
+                .long("history")
+                .global(true)
+                .help("List the entry chosen last time first, remembered per subcommand in $XDG_STATE_HOME/niri-action"),
+        )
+        .arg(
+            Arg::with_name("keep-going")
+                .long("keep-going")
+                .global(true)
+                .help("Send the remaining actions after one failed, e.g. for close-window --all, and report all failures at the end"),
+        )
+        .arg(
+            Arg::with_name("retry-on-failure")
+                .long("retry-on-failure")
+                .global(true)
+                .help("Show the picker again if niri refuses the action on the selection, at most 3 times"),
+        )
+        .arg(
+            Arg::with_name("print-request")
+                .long("print-request")
+                .global(true)
+                .help("Print each request to stderr as JSON before sending it to niri, e.g. for bug reports"),
+        )
+        .arg(
+            Arg::with_name("print-selection")
+                .long("print-selection")
+                .global(true)
+                .help("Print the id or name of the chosen entry (or the typed line) once the action succeeded"),
+        )
+        .arg(
+            Arg::with_name("connect-timeout")
+                .long("connect-timeout")
+                .takes_value(true)
+                .value_name("ms")
+                .validator(is_number)
+                .global(true)
+                .help("Give up connecting to niri after this many milliseconds"),
+        )
+        .arg(
+            Arg::with_name("strict")
+                .long("strict")
+                .global(true)
+                .help("Fail on unexpected niri responses instead of treating them as empty lists"),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .global(true)
+                .help("Print errors to stderr as JSON objects with an error message and kind"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .long("quiet")
+                .short("q")
+                .global(true)
+                .help("Only print errors and the requested output"),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .conflicts_with("quiet")
+                .global(true)
+                .help("Print debug messages to stderr"),
+        )
+        .subcommand(
+            SubCommand::with_name("focus-container")
+                .about("Focus window by name using fuzzel")
+                .arg(
+                    Arg::with_name("exclude-focused")
+                        .long("exclude-focused")
+                        .help("Leave the focused window out of the list"),
+                )
+                .arg(
+                    Arg::with_name("include-focused")
+                        .long("include-focused")
+                        .conflicts_with("exclude-focused")
+                        .help("List the focused window, overriding exclude-focused from the config"),
+                )
+                .arg(
+                    Arg::with_name("workspace")
+                        .long("workspace")
+                        .takes_value(true)
+                        .value_name("name|idx|id")
+                        .help("Only list windows on this workspace, a number is an index on the focused output first"),
+                )
+                .arg(
+                    Arg::with_name("current-workspace")
+                        .long("current-workspace")
+                        .conflicts_with("workspace")
+                        .help("Only list windows on the focused workspace"),
+                )
+                .arg(
+                    Arg::with_name("output-of-focused-window")
+                        .long("output-of-focused-window")
+                        .help("Only list windows on the output of the focused window"),
+                )
+                .arg(
+                    Arg::with_name("workspace-name")
+                        .long("workspace-name")
+                        .takes_value(true)
+                        .value_name("substring")
+                        .help("Only list windows on workspaces whose name contains this"),
+                )
+                .arg(
+                    Arg::with_name("exclude-app-id")
+                        .long("exclude-app-id")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .value_name("app_id")
+                        .help("Leave out windows with this app id, may be repeated; a window is listed only if it passes all filters"),
+                )
+                .arg(
+                    Arg::with_name("dedup-windows-by-app-id")
+                        .long("dedup-windows-by-app-id")
+                        .help("List one window per app id, the most recently focused one"),
+                )
+                .arg(
+                    Arg::with_name("mru")
+                        .long("mru")
+                        .help("Sort by focus, the most recently focused window first"),
+                )
+                .arg(
+                    Arg::with_name("focus-last")
+                        .long("focus-last")
+                        .help("Sort by focus with the previously focused window first and the focused one last, for alt-tab"),
+                )
+                .arg(
+                    Arg::with_name("stdin-items")
+                        .long("stdin-items")
+                        .help("Append `label<TAB>command` lines from stdin to the list, choosing one spawns the command"),
+                )
+                .arg(
+                    Arg::with_name("spawn-on-empty")
+                        .long("spawn-on-empty")
+                        .takes_value(true)
+                        .value_name("command")
+                        .help("Spawn this command instead of showing an empty list, split on whitespace"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("urgent")
+                .about("Focus the urgent window, pick one if there are several"),
+        )
         .subcommand(
-            SubCommand::with_name("focus-container").about("Focus window by name using fuzzel"),
+            SubCommand::with_name("steal-container")
+                .about("Steal window into current workspace")
+                .arg(
+                    Arg::with_name("focus")
+                        .long("focus")
+                        .help("Focus the stolen window"),
+                ),
         )
         .subcommand(
-            SubCommand::with_name("steal-container").about("Steal window into current workspace"),
+            SubCommand::with_name("close-window")
+                .about("Close a window, or every window matching the filters")
+                .arg(
+                    Arg::with_name("app-id")
+                        .long("app-id")
+                        .takes_value(true)
+                        .value_name("app_id")
+                        .help("Only list windows with this app id"),
+                )
+                .arg(
+                    Arg::with_name("title")
+                        .long("title")
+                        .takes_value(true)
+                        .value_name("substring")
+                        .help("Only list windows whose title contains this"),
+                )
+                .arg(
+                    Arg::with_name("all")
+                        .long("all")
+                        .help("Close all windows passing the filters without showing the picker"),
+                ),
         )
         .subcommand(
-            SubCommand::with_name("focus-workspace").about("Focus workspace by name using fuzzel"),
+            SubCommand::with_name("focus-workspace")
+                .about("Focus workspace by name using fuzzel")
+                .arg(
+                    Arg::with_name("non-empty")
+                        .long("non-empty")
+                        .alias("active-only")
+                        .help("Only list workspaces containing windows"),
+                )
+                .arg(
+                    Arg::with_name("index-prefix")
+                        .long("index-prefix")
+                        .help("Start labels with the workspace index instead of its id, with --auto-select only the focused output is listed"),
+                )
+                .arg(
+                    Arg::with_name("warp")
+                        .long("warp")
+                        .help("Move the pointer to the output of the focused workspace using warp-command"),
+                )
+                .arg(workspace_sort_arg()),
         )
         .subcommand(
             SubCommand::with_name("move-to-workspace")
-                .about("Move Currently focused container to workspace"),
+                .about("Move Currently focused container to workspace")
+                .arg(
+                    Arg::with_name("by-index")
+                        .long("by-index")
+                        .help("Target the workspace index on the focused monitor instead of the workspace id"),
+                )
+                .arg(
+                    Arg::with_name("smart-order")
+                        .long("smart-order")
+                        .help("List workspaces containing windows before empty ones"),
+                )
+                .arg(
+                    Arg::with_name("create")
+                        .long("create")
+                        .help("Create a workspace named after typed text matching no workspace and move the window there"),
+                )
+                .arg(
+                    Arg::with_name("and-focus-workspace")
+                        .long("and-focus-workspace")
+                        .help("Focus the target workspace once the window was moved"),
+                )
+                .arg(
+                    Arg::with_name("keep-focus")
+                        .long("keep-focus")
+                        .help("Focus the moved window again on its new workspace"),
+                )
+                .arg(workspace_sort_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("name-current-workspace")
+                .about("Name the focused workspace, with --no-exit-on-empty an empty name removes it"),
         )
         .subcommand(
             SubCommand::with_name("move-workspace-to-output")
                 .about("Move current workspace to output by name"),
         )
+        .subcommand(
+            SubCommand::with_name("focus-output")
+                .about("Focus an output picked by name, or the given one")
+                .arg(Arg::with_name("name").help("Connector name, e.g. DP-1, skips the picker")),
+        )
+        .subcommand(
+            SubCommand::with_name("move-column-to-monitor")
+                .about("Move current column to output by name"),
+        )
+        .subcommand(
+            SubCommand::with_name("set-output-scale")
+                .about("Set the scale of an output picked by name"),
+        )
+        .subcommand(
+            SubCommand::with_name("toggle-output")
+                .about("Turn an output picked by name on or off"),
+        )
+        .subcommand(
+            SubCommand::with_name("raw")
+                .about("Send a JSON encoded niri request and print the response")
+                .arg(Arg::with_name("request").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("Print the entries a picker would show, for scripts")
+                .arg(
+                    Arg::with_name("kind")
+                        .required(true)
+                        .possible_values(&["windows", "workspaces", "outputs"]),
+                )
+                .arg(
+                    Arg::with_name("output-format")
+                        .long("output-format")
+                        .takes_value(true)
+                        .possible_values(&["plain", "json", "null"])
+                        .default_value("plain")
+                        .help("One label per line, a JSON array of objects or NUL terminated labels"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("version")
+                .about("Print the versions of niri-action, niri-ipc and the running niri")
+                .after_help("With --json the versions are printed as a JSON object."),
+        )
+        .subcommand(
+            SubCommand::with_name("menu")
+                .about("Pick one of the lines on stdin and print it, like dmenu"),
+        )
         .subcommand(
             SubCommand::with_name("workspace-exec")
                 .about("execute command in workspace")
                 .arg(Arg::with_name("args").multiple(true)),
         )
+        .subcommand(
+            SubCommand::with_name("debug")
+                .setting(AppSettings::Hidden)
+                .about("Introspection for contributors")
+                .subcommand(
+                    SubCommand::with_name("actions")
+                        .about("List the subcommands and the niri actions they send"),
+                ),
+        )
+        .subcommands(
+            DIRECT_ACTIONS
+                .iter()
+                .map(|d| SubCommand::with_name(d.name).about(d.about)),
+        )
         .get_matches();
 
-    // establish a connection to i3 over a unix socket
-    let config = tilde(matches.value_of("confdir").unwrap()).to_string();
+    // before any early return, so e.g. --print-socket errors honour --json
+    QUIET.store(matches.is_present("quiet"), Ordering::Relaxed);
+    JSON.store(matches.is_present("json"), Ordering::Relaxed);
+    PRINT_REQUEST.store(matches.is_present("print-request"), Ordering::Relaxed);
+    VERBOSE.store(matches.is_present("verbose"), Ordering::Relaxed);
+    STRICT.store(matches.is_present("strict"), Ordering::Relaxed);
+
+    if let Some(debug) = matches.subcommand_matches("debug") {
+        if debug.subcommand_name() == Some("actions") {
+            print_actions();
+        }
+        return Ok(());
+    }
+
+    if matches.is_present("list-pickers") {
+        for name in picker::NAMES {
+            let found = match picker::on_path(name) {
+                true => "found",
+                false => "not found",
+            };
+            println!("{name}: {found}");
+        }
+        return Ok(());
+    }
+    // the path Socket::connect uses, there's no fallback
+    if matches.is_present("print-socket") {
+        let path = std::env::var_os(niri_ipc::socket::SOCKET_PATH_ENV).ok_or_else(|| NiriIPCError::SocketConnect {
+            err: format!("{} is not set, are you running this within niri?", niri_ipc::socket::SOCKET_PATH_ENV),
+        })?;
+        println!("{}", Path::new(&path).display());
+        return Ok(());
+    }
+
+    let confdir = tilde(matches.value_of("confdir").unwrap()).to_string();
+    let config = match matches.value_of("config") {
+        Some(path) => Config::load(Path::new(&tilde(path).to_string()))?,
+        None => {
+            let path = Path::new(&confdir).join("config.toml");
+            match path.exists() {
+                true => Config::load(&path)?,
+                false => Config::default(),
+            }
+        }
+    };
+    // command line options win over the section of the subcommand, which wins over the global
+    // section of the config file
+    let section = matches.subcommand_name().unwrap_or("");
+    let setting = |name: &str| {
+        matches
+            .value_of(name)
+            .or_else(|| config.get(section, name))
+            .or_else(|| config.get("", name))
+    };
+    // on/off settings: the flag if given, otherwise the config value as for `setting`
+    let flag = |name: &str| -> Result<bool, Error> {
+        match matches.is_present(name) {
+            true => Ok(true),
+            false => setting(name).map_or(Ok(false), |value| parse_setting(name, value)),
+        }
+    };
+
+    let picker_name = setting("picker").unwrap_or("fuzzel");
+    let icons = flag("icons")?;
+    let style = picker::Style {
+        width: setting("width").map(|n| parse_setting("width", n)).transpose()?,
+        lines: setting("lines").map(|n| parse_setting("lines", n)).transpose()?,
+        font: setting("font").map(|f| f.to_string()),
+        colors: setting("colors").map(parse_colors).transpose()?.unwrap_or_default(),
+        match_mode: match setting("match") {
+            Some(mode) if !picker::MATCH_MODES.contains(&mode) => Err(format_err!("Invalid value '{}' for match", mode))?,
+            mode => mode.map(|m| m.to_string()),
+        },
+        initial: setting("initial").map(|i| i.to_string()),
+        auto_select: flag("auto-select")?,
+        auto_width: flag("auto-width")?,
+        counter: flag("counter")?,
+        icons,
+        icon_theme: match icons {
+            true => setting("icon-theme").map(|t| t.to_string()).or_else(icons::default_theme),
+            false => None,
+        },
+        no_config: flag("picker-no-config")?,
+        retry: flag("retry-picker")?,
+        hidden: flag("hidden")?,
+        null_input: flag("null-input")?,
+    };
+    let picker = picker::from_name(picker_name, style, setting("terminal")).ok_or_else(|| NiriIPCError::PickerNotFound { picker: picker_name.to_string() })?;
+    let prompt = setting("prompt").map(|p| p.to_string());
+    let prompt_count = flag("prompt-count")?;
+    if let Some("menu") = matches.subcommand_name() {
+        return menu(picker.as_ref(), prompt.as_deref(), prompt_count);
+    }
+    let labels = LabelOptions {
+        max_title_len: setting("max-title-len").map(|n| parse_setting("max-title-len", n)).transpose()?,
+        workspace_index: flag("include-index-in-window-label")?,
+        include_pid: flag("include-pid")?,
+        include_app_id: flag("include-app-id")?,
+        trim_app_id: flag("trim-app-id")?,
+        icons: match (picker.icons(), setting("app-id-icon-map")) {
+            (false, _) => None,
+            (true, Some(path)) => Some(Icons::load_map(Path::new(&tilde(path).to_string()))?),
+            (true, None) => Some(Icons::default()),
+        },
+        keep_newlines: picker.null_input(),
+        missing: setting("missing-label").unwrap_or("<unknown>").to_string(),
+    };
+
+    let history = match flag("history")? {
+        true => Some(History::load(section)),
+        false => None,
+    };
+    let retry_on_failure = flag("retry-on-failure")?;
+
+    // establish a connection to niri over a unix socket
     let mut state = ApplicationState {
-        socket: &mut niri_ipc::socket::Socket::connect()?,
-        confdir: Path::new(&config),
+        socket: &mut connect(setting("connect-timeout").map(|n| parse_setting("connect-timeout", n)).transpose()?)?,
+        confdir: Path::new(&confdir),
+        picker,
+        prompt,
+        prompt_count,
+        placeholder: setting("placeholder").map(|p| p.to_string()),
+        exit_on_empty: match (matches.is_present("exit-on-empty"), matches.is_present("no-exit-on-empty")) {
+            (true, _) => true,
+            (_, true) => false,
+            _ => parse_setting("exit-on-empty", setting("exit-on-empty").unwrap_or("true"))?,
+        },
+        no_picker_on_single: flag("no-picker-on-single")?,
+        select_index: match flag("select-first")? {
+            true => Some(1),
+            false => setting("select-index").map(|n| parse_setting("select-index", n)).transpose()?,
+        },
+        keep_going: flag("keep-going")?,
+        labels,
+        config,
+        selected: RefCell::new(None),
+        history,
     };
 
-    match matches.subcommand_name() {
-        Some("focus-container") => state.focus_container_by_id(),
-        Some("steal-container") => state.steal_container_by_id(),
-        Some("focus-workspace") => state.focus_workspace_by_name(),
-        Some("move-to-workspace") => state.move_to_workspace_by_name(),
-        Some("move-workspace-to-output") => state.move_workspace_to_output(),
-        Some("workspace-exec") => state.workspace_exec(&matches),
-        _ => Ok(()),
+    // a failed action most likely means the window or output went away, offer the fresh list
+    let mut retries = match retry_on_failure {
+        true => MAX_ACTION_RETRIES,
+        false => 0,
+    };
+    let result = loop {
+        let result = state.dispatch(&matches);
+        match &result {
+            Err(err) if retries > 0 && state.selected.borrow().is_some() && matches!(err.downcast_ref(), Some(NiriIPCError::ActionFailed { .. } | NiriIPCError::WindowGone { .. })) => {
+                warn(&format!("{err}, pick again"));
+                state.selected.replace(None);
+                retries -= 1;
+            }
+            _ => break result,
+        }
+    };
+    if let (Ok(()), Some(selected)) = (&result, state.selected.take()) {
+        if matches.is_present("print-selection") {
+            println!("{selected}");
+        }
+        if let Some(history) = state.history
+            && let Err(err) = history.save(&selected)
+        {
+            warn(&err.to_string());
+        }
     }
+    result
 }
 
 impl ApplicationState<'_> {
-    fn focus_container_by_id(&mut self) -> Result<(), Error> {
-        let windows = get_windows(self.socket)?;
+    /// Runs the subcommand in `matches`.
+    fn dispatch(&mut self, matches: &ArgMatches) -> Result<(), Error> {
+        let Some(name) = matches.subcommand_name() else { return Ok(()) };
+        if let Some(h) = HANDLERS.iter().find(|h| h.name == name) {
+            return (h.run)(self, matches);
+        }
+        match DIRECT_ACTIONS.iter().find(|d| d.name == name) {
+            Some(d) => self.socket.run_action(Request::Action((d.action)())),
+            None => Ok(()),
+        }
+    }
 
-        let id = fuzzel_get_selection_id(&windows).parse::<u64>()?;
+    fn focus_container_by_id(&mut self, matches: &ArgMatches) -> Result<(), Error> {
+        let section = "focus-container";
+        let matches = matches.subcommand_matches(section).unwrap();
+        let workspace = match matches.value_of("workspace") {
+            Some(reference) => Some(resolve_workspace(self.socket, reference)?),
+            None if self.switch(matches, section, "current-workspace", None)? => Some(get_current_workspace(self.socket)?),
+            None => None,
+        };
+        // the focused window is on the focused workspace
+        let mut workspaces = match self.switch(matches, section, "output-of-focused-window", None)? {
+            true => {
+                let current = get_current_workspace(self.socket)?;
+                Some(get_sibling_workspaces(self.socket, current)?)
+            }
+            false => None,
+        };
+        if let Some(substring) = self.option(matches, section, "workspace-name").map(|s| s.to_string()) {
+            let named = get_workspaces_named(self.socket, &substring)?;
+            workspaces = Some(match workspaces {
+                Some(ids) => ids.intersection(&named).copied().collect(),
+                None => named,
+            });
+        }
+        let exclude_app_ids = match matches.values_of("exclude-app-id") {
+            Some(values) => values.map(|v| v.to_string()).collect(),
+            None => self.option(matches, section, "exclude-app-id").map(split_list).unwrap_or_default(),
+        };
+        let list = WindowListOptions {
+            exclude_focused: self.switch(matches, section, "exclude-focused", Some("include-focused"))?,
+            workspace,
+            workspaces,
+            exclude_app_ids,
+            dedup_app_ids: self.switch(matches, section, "dedup-windows-by-app-id", None)?,
+            mru: self.switch(matches, section, "mru", None)?,
+            focus_last: self.switch(matches, section, "focus-last", None)?,
+            ..Default::default()
+        };
+        let windows = get_windows(self.socket, &self.labels, &list)?;
+        if let (true, Some(command)) = (windows.is_empty(), self.option(matches, section, "spawn-on-empty")) {
+            let command = command.split_whitespace().map(|word| word.to_string()).collect();
+            return self.socket.run_action(Request::Action(Action::Spawn { command }));
+        }
+        if !matches.is_present("stdin-items") {
+            let id = self.pick_id(&windows)?;
+            return self.socket.run_action(Request::Action(Action::FocusWindow { id }));
+        }
+
+        // pseudo-entries are told apart from windows by matching the selection against both lists
+        let items = read_stdin_items()?;
+        let mut labels = self.labels_of(&windows);
+        labels.extend(items.iter().map(|(_, label)| label.clone()));
+        let selection = self.pick(&labels, "window")?;
+        if let Some(&id) = match_entry(&windows, &selection) {
+            self.selected.replace(Some(id.to_string()));
+            return self.socket.run_action(Request::Action(Action::FocusWindow { id }));
+        }
+        match match_entry(&items, &selection) {
+            Some(command) => self.socket.run_action(Request::Action(Action::SpawnSh { command: command.clone() })),
+            None => Err(NiriIPCError::NoMatch { what: "window".to_string(), input: selection })?,
+        }
+    }
+
+    fn focus_urgent(&mut self) -> Result<(), Error> {
+        let list = WindowListOptions {
+            urgent_only: true,
+            ..Default::default()
+        };
+        let windows = get_windows(self.socket, &self.labels, &list)?;
+
+        let id = match windows.len() {
+            0 => {
+                info("No urgent windows");
+                return Ok(());
+            }
+            1 => {
+                self.selected.replace(Some(windows[0].0.to_string()));
+                windows[0].0
+            }
+            _ => self.pick_id(&windows)?,
+        };
         self.socket.run_action(Request::Action(Action::FocusWindow { id }))
     }
 
-    fn steal_container_by_id(&mut self) -> Result<(), Error> {
-        let windows = get_windows(self.socket)?;
+    fn steal_container_by_id(&mut self, matches: &ArgMatches) -> Result<(), Error> {
+        let section = "steal-container";
+        let matches = matches.subcommand_matches(section).unwrap();
+        // a single action, niri focuses the moved window on its new workspace
+        let focus = self.switch(matches, section, "focus", None)?;
+        let windows = get_windows(self.socket, &self.labels, &WindowListOptions::default())?;
         let ws = get_current_workspace(self.socket)?;
 
-        let id = fuzzel_get_selection_id(&windows).parse::<u64>()?;
-        self.socket.run_action(Request::Action(Action::MoveWindowToWorkspace { window_id: Some(id), reference: niri_ipc::WorkspaceReferenceArg::Id(ws), focus: false } ))
+        let id = self.pick_id(&windows)?;
+        self.socket.run_action(Request::Action(Action::MoveWindowToWorkspace { window_id: Some(id), reference: niri_ipc::WorkspaceReferenceArg::Id(ws), focus } ))
     }
 
-    fn focus_workspace_by_name(&mut self) -> Result<(), Error> {
-        let work_names = get_workspaces(self.socket)?;
+    fn close_window(&mut self, matches: &ArgMatches) -> Result<(), Error> {
+        let section = "close-window";
+        let matches = matches.subcommand_matches(section).unwrap();
+        let list = WindowListOptions {
+            app_id: self.option(matches, section, "app-id").map(|a| a.to_string()),
+            title: self.option(matches, section, "title").map(|t| t.to_string()),
+            ..Default::default()
+        };
+        if !matches.is_present("all") {
+            let windows = get_windows(self.socket, &self.labels, &list)?;
+            let id = self.pick_id(&windows)?;
+            return self.socket.run_action(Request::Action(Action::CloseWindow { id: Some(id) }));
+        }
+        // not closing every window by accident
+        if list.app_id.is_none() && list.title.is_none() {
+            return Err(format_err!("--all needs --app-id or --title"));
+        }
+        let windows = get_windows(self.socket, &self.labels, &list)?;
+        self.run_actions(windows.iter().map(|&(id, _)| (id.to_string(), Request::Action(Action::CloseWindow { id: Some(id) }))).collect())
+    }
+
+    fn focus_workspace_by_name(&mut self, matches: &ArgMatches) -> Result<(), Error> {
+        let section = "focus-workspace";
+        let matches = matches.subcommand_matches(section).unwrap();
+        let list = WorkspaceListOptions {
+            non_empty: self.switch(matches, section, "non-empty", None)?,
+            index_prefix: self.switch(matches, section, "index-prefix", None)?,
+            sort: WorkspaceSort::from_name(self.option(matches, section, "sort"))?,
+            ..Default::default()
+        };
+        // quick mode: typing the index leaves a single entry, so only the focused output is listed
+        // and its name, which may hold digits, is left out of the labels
+        let list = WorkspaceListOptions {
+            focused_output: list.index_prefix && self.switch(matches, section, "auto-select", None)?,
+            ..list
+        };
+        let work_names = get_workspaces(self.socket, &self.labels, &list)?;
 
 
-        let ws = fuzzel_get_selection_id_or_entry(&work_names);
-        println!("{ws:?} for {work_names:?}");
+        let ws = self.pick_id_or_entry(&work_names, "workspace")?;
+        debug(&format!("{ws:?} for {work_names:?}"));
         match ws.id {
             Some(s) => {
-                self.socket.run_action(Request::Action(Action::FocusWorkspace { reference: niri_ipc::WorkspaceReferenceArg::Id(s) }))
+                self.socket.run_action(Request::Action(Action::FocusWorkspace { reference: niri_ipc::WorkspaceReferenceArg::Id(s) }))?
             }
             None => {
-                let id = work_names.last().expect("No workspaces").split(":").next().expect("Can't split out id").to_string().parse::<u64>()?;
-                self.socket.run_action(Request::Action(Action::FocusWorkspace { reference: niri_ipc::WorkspaceReferenceArg::Id(id) }))?;
-                self.socket.run_action(Request::Action(Action::SetWorkspaceName { name: ws.entry, workspace: Some(niri_ipc::WorkspaceReferenceArg::Id(id)) }))
+                let id = self.new_workspace_id()?;
+                self.focus_and_name_workspace(id, ws.entry)?
             }
         }
+        match self.switch(matches, section, "warp", None)? {
+            true => self.warp_to_focused_output(matches),
+            false => Ok(()),
+        }
     }
 
-    fn move_to_workspace_by_name(&mut self) -> Result<(), Error> {
-        let work_names = get_workspaces(self.socket)?;
+    /// niri has no IPC action to move the pointer, so this runs the `warp-command` helper with
+    /// the center of the focused output in logical coordinates.
+    fn warp_to_focused_output(&mut self, matches: &ArgMatches) -> Result<(), Error> {
+        let output = match self.socket.query(Request::FocusedOutput)? {
+            Some(Response::FocusedOutput(Some(output))) => output,
+            _ => Err(format_err!("No focused output to warp to"))?,
+        };
+        let logical = output.logical.ok_or_else(|| format_err!("Output {} is disabled", output.name))?;
+        let x = logical.x + logical.width as i32 / 2;
+        let y = logical.y + logical.height as i32 / 2;
 
-        let space = fuzzel_get_selection_id(&work_names).parse::<u64>()?;
-        self.socket.run_action(Request::Action(Action::MoveWindowToWorkspace { window_id: None, reference: niri_ipc::WorkspaceReferenceArg::Id(space), focus: false } ))
+        let template = matches
+            .value_of("warp-command")
+            .or_else(|| self.config.get("", "warp-command"))
+            .unwrap_or("ydotool mousemove --absolute -x {x} -y {y}");
+        let mut args = template
+            .split_whitespace()
+            .map(|arg| arg.replace("{x}", &x.to_string()).replace("{y}", &y.to_string()));
+        let binary = args.next().ok_or_else(|| format_err!("Empty warp-command"))?;
+        let status = std::process::Command::new(&binary)
+            .args(args)
+            .status()
+            .map_err(|e| format_err!("Can't run {}: {}", binary, e))?;
+        // e.g. ydotool without its daemon
+        if !status.success() {
+            Err(format_err!("{} failed ({})", binary, status))?
+        }
+        Ok(())
+    }
+
+    /// Id of the empty workspace niri keeps at the end of the focused output, which becomes a new
+    /// workspace once it gets a name or a window.
+    fn new_workspace_id(&mut self) -> Result<u64, Error> {
+        // the trailing empty workspace may have been filtered out of the picker
+        let workspaces = match self.socket.query(Request::Workspaces)? {
+            Some(Response::Workspaces(s)) => s,
+            other => unexpected(other)?,
+        };
+        let output = workspaces.iter().find(|x| x.is_focused).ok_or(NiriIPCError::NoFocusedWorkspace)?.output.clone();
+        Ok(workspaces
+            .iter()
+            .filter(|x| x.output == output)
+            .max_by_key(|x| x.idx)
+            .ok_or_else(|| format_err!("No workspaces"))?
+            .id)
+    }
+
+    /// Focuses workspace `id` and names it. The rename is only sent once focusing succeeded, the
+    /// first error is returned otherwise, `--keep-going` doesn't apply to this pair.
+    fn focus_and_name_workspace(&mut self, id: u64, name: String) -> Result<(), Error> {
+        self.socket.run_action(Request::Action(Action::FocusWorkspace { reference: niri_ipc::WorkspaceReferenceArg::Id(id) }))?;
+        self.socket.run_action(Request::Action(Action::SetWorkspaceName { name, workspace: Some(niri_ipc::WorkspaceReferenceArg::Id(id)) }))
+    }
+
+    /// Sends `(label, request)` pairs in order, stopping at the first failure. With `--keep-going`
+    /// all of them are sent and the failures are reported together at the end, each after the
+    /// label of its request.
+    fn run_actions(&mut self, requests: Vec<(String, Request)>) -> Result<(), Error> {
+        if !self.keep_going {
+            return requests.into_iter().try_for_each(|(_, request)| self.socket.run_action(request));
+        }
+        let total = requests.len();
+        let mut failures: Vec<(String, Error)> = requests
+            .into_iter()
+            .filter_map(|(label, request)| self.socket.run_action(request).err().map(|err| (label, err)))
+            .collect();
+        match (failures.len(), total) {
+            (0, _) => Ok(()),
+            // nothing to tell apart, keeps the kind and exit code of the error
+            (1, 1) => Err(failures.remove(0).1),
+            (n, _) => {
+                let messages: Vec<String> = failures.iter().map(|(label, err)| format!("{label}: {err}")).collect();
+                Err(format_err!("{} of {} actions failed: {}", n, total, messages.join("; ")))
+            }
+        }
+    }
+
+    /// Asks for a name for the focused workspace, with `--no-exit-on-empty` an empty name removes
+    /// the current one.
+    fn name_current_workspace(&mut self) -> Result<(), Error> {
+        let id = get_current_workspace(self.socket)?;
+        let name = self.prompt_text("name> ")?;
+        let workspace = niri_ipc::WorkspaceReferenceArg::Id(id);
+        match name.trim() {
+            "" => self.socket.run_action(Request::Action(Action::UnsetWorkspaceName { reference: Some(workspace) })),
+            name => self.socket.run_action(Request::Action(Action::SetWorkspaceName { name: name.to_string(), workspace: Some(workspace) })),
+        }
+    }
+
+    fn move_to_workspace_by_name(&mut self, matches: &ArgMatches) -> Result<(), Error> {
+        let section = "move-to-workspace";
+        let matches = matches.subcommand_matches(section).unwrap();
+        let by_index = self.switch(matches, section, "by-index", None)?;
+        let list = WorkspaceListOptions {
+            index_prefix: by_index,
+            occupied_first: self.switch(matches, section, "smart-order", None)?,
+            sort: WorkspaceSort::from_name(self.option(matches, section, "sort"))?,
+            ..Default::default()
+        };
+        let work_names = get_workspaces(self.socket, &self.labels, &list)?;
+
+        let ws = self.pick_id_or_entry(&work_names, "workspace")?;
+        let reference = match (by_index, ws.id) {
+            // the list spans all outputs, an index only names a workspace on the focused one
+            (_, Some(id)) => niri_ipc::WorkspaceReferenceArg::Id(id),
+            // a typed number, niri looks the index up on the focused output
+            (true, None) => {
+                let idx = label_id(&ws.entry, "workspace index")?;
+                niri_ipc::WorkspaceReferenceArg::Index(u8::try_from(idx).map_err(|_| NiriIPCError::ParseId { what: "workspace index".to_string(), selection: ws.entry.clone() })?)
+            }
+            (false, None) => match label_id(&ws.entry, "workspace id") {
+                Ok(id) => niri_ipc::WorkspaceReferenceArg::Id(id),
+                // a name, create the workspace like focus-workspace does
+                Err(_) if self.switch(matches, section, "create", None)? => {
+                    let id = self.new_workspace_id()?;
+                    self.socket.run_action(Request::Action(Action::SetWorkspaceName { name: ws.entry.clone(), workspace: Some(niri_ipc::WorkspaceReferenceArg::Id(id)) }))?;
+                    niri_ipc::WorkspaceReferenceArg::Id(id)
+                }
+                Err(err) => return Err(err),
+            },
+        };
+        let window = get_focused_window(self.socket)?;
+        let current = window.as_ref().and_then(|window| window.workspace_id);
+        if current.is_some() && current == target_workspace_id(self.socket, &reference)? {
+            debug("The focused window is on the target workspace already, not moving it");
+            return Ok(());
+        }
+        let mut requests = vec![("move window".to_string(), Request::Action(Action::MoveWindowToWorkspace { window_id: None, reference: reference.clone(), focus: false }))];
+        // switches the view, the moved window keeps its focus state on the target either way
+        if self.switch(matches, section, "and-focus-workspace", None)? {
+            requests.push(("focus workspace".to_string(), Request::Action(Action::FocusWorkspace { reference })));
+        }
+        // niri may focus another window after the move, going by id brings the moved one back
+        if let (true, Some(window)) = (self.switch(matches, section, "keep-focus", None)?, window) {
+            requests.push((format!("focus window {}", window.id), Request::Action(Action::FocusWindow { id: window.id })));
+        }
+        self.run_actions(requests)
     }
 
     fn move_workspace_to_output(&mut self) -> Result<(), Error> {
-        let outputs = get_outputs(self.socket)?;
-        let output = fuzzel_get_selection_id(&outputs);
-        self.socket.run_action(Request::Action(Action::MoveWorkspaceToMonitor { output, reference: None }))
+        match self.select_output()? {
+            Some(output) => self.socket.run_action(Request::Action(Action::MoveWorkspaceToMonitor { output, reference: None })),
+            None => Ok(()),
+        }
+    }
+
+    fn focus_output(&mut self, matches: &ArgMatches) -> Result<(), Error> {
+        let matches = matches.subcommand_matches("focus-output").unwrap();
+        let output = match matches.value_of("name") {
+            Some(name) => canonical_output(&get_outputs(self.socket, &self.labels)?, name)?,
+            None => {
+                let Some(output) = self.select_output()? else { return Ok(()) };
+                output
+            }
+        };
+        self.socket.run_action(Request::Action(Action::FocusMonitor { output }))
+    }
+
+    fn move_column_to_monitor(&mut self) -> Result<(), Error> {
+        match self.select_output()? {
+            Some(output) => self.socket.run_action(Request::Action(Action::MoveColumnToMonitor { output })),
+            None => Ok(()),
+        }
+    }
+
+    /// Resolves a valued option of the `section` subcommand: `--<name>` if given, otherwise the
+    /// config key `<name>` of the `[section]` table or the global one.
+    fn option<'m>(&'m self, matches: &'m ArgMatches, section: &str, name: &str) -> Option<&'m str> {
+        matches
+            .value_of(name)
+            .or_else(|| self.config.get(section, name))
+            .or_else(|| self.config.get("", name))
+    }
+
+    /// Resolves an on/off option of the `section` subcommand: `--<name>` or `--<negation>` if
+    /// given, otherwise the config key `<name>` as for [`Self::option`].
+    fn switch(&self, matches: &ArgMatches, section: &str, name: &str, negation: Option<&str>) -> Result<bool, Error> {
+        if matches.is_present(name) {
+            return Ok(true);
+        }
+        if negation.is_some_and(|negation| matches.is_present(negation)) {
+            return Ok(false);
+        }
+        match self.option(matches, section, name) {
+            Some(value) => parse_setting(name, value),
+            None => Ok(false),
+        }
+    }
+
+    /// Lets the user pick an output, returns `None` if there is nothing to pick.
+    fn select_output(&mut self) -> Result<Option<String>, Error> {
+        let outputs = get_outputs(self.socket, &self.labels)?;
+        if outputs.is_empty() {
+            info("No outputs available");
+            return Ok(None);
+        }
+        let labels = self.labels_of(&outputs);
+        let selection = self.pick(&labels, "output")?;
+        match match_entry(&outputs, &selection) {
+            Some(name) => {
+                self.selected.replace(Some(name.clone()));
+                Ok(Some(name.clone()))
+            }
+            // typed text, e.g. just the connector name, blank text is as good as cancelling
+            None => match parse_output_name(&selection) {
+                Some(name) => canonical_output(&outputs, &name).map(Some),
+                None => Err(NiriIPCError::PickerCancelled)?,
+            },
+        }
+    }
+
+    /// The labels of `entries` in order, except that the entry chosen last time comes first with
+    /// `--history`.
+    fn labels_of<K: ToString>(&self, entries: &[(K, String)]) -> Vec<String> {
+        let last = self.history.as_ref().and_then(|history| history.last());
+        let mut labels: Vec<(bool, String)> = entries
+            .iter()
+            .map(|(key, label)| (last != Some(key.to_string().as_str()), label.clone()))
+            .collect();
+        labels.sort_by_key(|(other, _)| *other);
+        labels.into_iter().map(|(_, label)| label).collect()
+    }
+
+    /// Shows `input` in the configured picker, fails with `PickerCancelled` if the user cancelled.
+    /// `kind` names the entries for the counting prompt.
+    fn pick(&self, input: &[String], kind: &str) -> Result<String, Error> {
+        let selection = match (self.select_index, self.no_picker_on_single, input) {
+            (Some(index), _, _) => {
+                let entry = index.checked_sub(1).and_then(|i| input.get(i)).ok_or_else(|| {
+                    format_err!("Can't select {} {}, the list has {} entries", kind, index, input.len())
+                })?;
+                // as printed by the picker, without the metadata suffix
+                entry.split('\0').next().map(|label| label.to_string())
+            }
+            (None, true, [only]) => only.split('\0').next().map(|label| label.to_string()),
+            _ => {
+                let prompt = picker_prompt(self.prompt.as_deref(), kind, self.prompt_count.then_some(input.len()));
+                self.picker.pick(input, prompt.as_deref())?
+            }
+        };
+        let selection = selection.ok_or(NiriIPCError::PickerCancelled)?;
+        self.selected.replace(Some(selection.clone()));
+        Ok(selection)
+    }
+
+    /// Asks for free text, fails with `PickerCancelled` if the user cancelled, which includes
+    /// confirming an empty input unless `--no-exit-on-empty` was given.
+    fn prompt_text(&self, prompt: &str) -> Result<String, Error> {
+        let text = self.picker.prompt(prompt, self.placeholder.as_deref())?;
+        match text {
+            Some(text) if !(self.exit_on_empty && text.trim().is_empty()) => Ok(text),
+            _ => Err(NiriIPCError::PickerCancelled)?,
+        }
+    }
+
+    /// Lets the user pick one of the labelled windows and returns its id. The selected line has to
+    /// match one of the labels, typed text is rejected.
+    fn pick_id(&self, windows: &[(u64, String)]) -> Result<u64, Error> {
+        match self.pick_id_or_entry(windows, "window")? {
+            IDorEntry { id: Some(id), .. } => Ok(id),
+            IDorEntry { id: None, entry } => Err(NiriIPCError::NoMatch { what: "window".to_string(), input: entry })?,
+        }
+    }
+
+    /// Lets the user pick one of the labelled `entries`. The result carries the id of the chosen
+    /// entry, or only the typed text if it matches no label.
+    fn pick_id_or_entry(&self, entries: &[(u64, String)], kind: &str) -> Result<IDorEntry, Error> {
+        let labels = self.labels_of(entries);
+        let selection = self.pick(&labels, kind)?;
+        let id = match_entry(entries, &selection).copied();
+        if let Some(id) = id {
+            self.selected.replace(Some(id.to_string()));
+        }
+        Ok(IDorEntry { id, entry: selection })
+    }
+
+    fn set_output_scale(&mut self) -> Result<(), Error> {
+        let Some(output) = self.select_output()? else { return Ok(()) };
+        let scale = parse_scale(&self.prompt_text("scale> ")?)?;
+        let action = niri_ipc::OutputAction::Scale { scale };
+        self.socket.run_action(Request::Output { output, action })
+    }
+
+    fn toggle_output(&mut self) -> Result<(), Error> {
+        let Some(name) = self.select_output()? else { return Ok(()) };
+        let outputs = get_output_info(self.socket)?;
+        let output = outputs.get(&name).ok_or_else(|| format_err!("Output {} is not connected", name))?;
+
+        // disabled outputs have no logical size
+        let action = match output.logical {
+            Some(_) => {
+                if outputs.values().filter(|x| x.logical.is_some()).count() == 1 {
+                    warn(&format!("{name} is the only active output"));
+                    let answer = self.picker.pick(&["no".to_string(), "yes".to_string()], Some("Turn it off anyway? "))?;
+                    if answer.as_deref() != Some("yes") {
+                        return Ok(());
+                    }
+                }
+                niri_ipc::OutputAction::Off
+            }
+            None => niri_ipc::OutputAction::On,
+        };
+        self.socket.run_action(Request::Output { output: name, action })
+    }
+
+    fn list(&mut self, matches: &ArgMatches) -> Result<(), Error> {
+        let matches = matches.subcommand_matches("list").unwrap();
+        let format = matches.value_of("output-format").unwrap();
+        // only plain output is line based, the others carry titles with their newlines
+        self.labels.keep_newlines = format != "plain";
+        let entries: Vec<(serde_json::Value, String)> = match matches.value_of("kind").unwrap() {
+            "windows" => get_windows(self.socket, &self.labels, &WindowListOptions::default())?
+                .into_iter()
+                .map(|(id, label)| (id.into(), label))
+                .collect(),
+            "workspaces" => get_workspaces(self.socket, &self.labels, &WorkspaceListOptions::default())?
+                .into_iter()
+                .map(|(id, label)| (id.into(), label))
+                .collect(),
+            _ => get_outputs(self.socket, &self.labels)?
+                .into_iter()
+                .map(|(name, label)| (name.into(), label))
+                .collect(),
+        };
+        // icon suffixes are for the picker only
+        let labels = entries.iter().map(|(key, label)| (key, label.split('\0').next().unwrap_or("")));
+        match format {
+            "json" => {
+                let key = match matches.value_of("kind") {
+                    Some("outputs") => "name",
+                    _ => "id",
+                };
+                let array: Vec<serde_json::Value> = labels.map(|(k, label)| serde_json::json!({ key: k, "label": label })).collect();
+                println!("{}", serde_json::Value::Array(array));
+            }
+            "null" => {
+                let mut stdout = std::io::stdout().lock();
+                for (_, label) in labels {
+                    write!(stdout, "{label}\0")?;
+                }
+            }
+            _ => {
+                for (_, label) in labels {
+                    println!("{label}");
+                }
+                // on stderr, scripts reading the list don't have to skip it
+                if !QUIET.load(Ordering::Relaxed) {
+                    eprintln!("{}", self.list_summary(matches.value_of("kind").unwrap(), entries.len())?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts for the end of a plain list, e.g. "(5 workspaces, 2 non-empty)".
+    fn list_summary(&mut self, kind: &str, count: usize) -> Result<String, Error> {
+        let detail = match kind {
+            "windows" => {
+                let urgent = get_windows(self.socket, &self.labels, &WindowListOptions { urgent_only: true, ..Default::default() })?.len();
+                format!("{urgent} urgent")
+            }
+            "workspaces" => {
+                let occupied = get_occupied_workspaces(self.socket)?.len();
+                format!("{occupied} non-empty")
+            }
+            _ => {
+                let enabled = get_output_info(self.socket)?.values().filter(|x| x.logical.is_some()).count();
+                format!("{enabled} enabled")
+            }
+        };
+        let noun = match count {
+            1 => kind.trim_end_matches('s'),
+            _ => kind,
+        };
+        Ok(format!("({count} {noun}, {detail})"))
+    }
+
+    fn version(&mut self, matches: &ArgMatches) -> Result<(), Error> {
+        let matches = matches.subcommand_matches("version").unwrap();
+        let niri = match self.socket.query(Request::Version)? {
+            Some(Response::Version(version)) => version,
+            x => Err(NiriIPCError::UnhandledError { err: format!("Got result for {:?}", x) })?,
+        };
+        match matches.is_present("json") {
+            true => println!("{}", serde_json::json!({ "niri-action": VERSION, "niri-ipc": NIRI_IPC_VERSION, "niri": niri })),
+            false => {
+                println!("niri-action {VERSION}");
+                println!("niri-ipc {NIRI_IPC_VERSION}");
+                println!("niri {niri}");
+            }
+        }
+        Ok(())
+    }
+
+    fn raw(&mut self, matches: &ArgMatches) -> Result<(), Error> {
+        let matches = matches.subcommand_matches("raw").unwrap();
+        let request: Request = serde_json::from_str(matches.value_of("request").unwrap())
+            .map_err(|e| format_err!("Invalid request: {}", e))?;
+        print_request(&request);
+        match self.socket.send(request)? {
+            Ok(response) => {
+                println!("{}", serde_json::to_string(&response)?);
+                Ok(())
+            }
+            Err(err) => Err(NiriIPCError::UnhandledError { err })?,
+        }
     }
 
     fn workspace_exec(&mut self, matches: &ArgMatches) -> Result<(), Error> {
@@ -188,58 +1665,414 @@ impl ApplicationState<'_> {
     }
 }
 
-fn get_current_workspace_name(socket: &mut niri_ipc::socket::Socket) -> Result<String, Error> {
+/// Replaces newlines, tabs and other control characters, as pickers read one entry per line.
+fn sanitize_label(label: &str) -> String {
+    label
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect()
+}
+
+/// Shortens `label` to at most `max` characters, marking the cut with an ellipsis.
+fn truncate(label: &str, max: Option<usize>) -> String {
+    match max {
+        Some(max) if label.chars().count() > max => {
+            let mut short: String = label.chars().take(max.saturating_sub(1)).collect();
+            short.push('…');
+            short
+        }
+        _ => label.to_string(),
+    }
+}
+
+/// Connects to niri, giving up after `timeout` milliseconds if given. The connection attempt
+/// runs on its own thread as a stuck niri can leave it hanging indefinitely.
+fn connect(timeout: Option<u64>) -> Result<niri_ipc::socket::Socket, Error> {
+    let Some(timeout) = timeout else {
+        return Ok(niri_ipc::socket::Socket::connect().map_err(|e| NiriIPCError::SocketConnect { err: e.to_string() })?);
+    };
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || sender.send(niri_ipc::socket::Socket::connect()));
+    match receiver.recv_timeout(std::time::Duration::from_millis(timeout)) {
+        Ok(Ok(socket)) => Ok(socket),
+        Ok(Err(e)) => Err(NiriIPCError::SocketConnect { err: e.to_string() })?,
+        Err(_) => Err(NiriIPCError::SocketConnect { err: format!("timed out after {timeout} ms") })?,
+    }
+}
+
+/// Handles a response of the wrong type to a query. It is treated like an empty result unless
+/// `--strict` was given, which helps to spot protocol changes after niri upgrades.
+fn unexpected<T: Default>(response: Option<Response>) -> Result<T, Error> {
+    match STRICT.load(Ordering::Relaxed) {
+        true => Err(NiriIPCError::UnhandledError { err: format!("Unexpected response {:?}", response) })?,
+        false => Ok(T::default()),
+    }
+}
+
+fn get_current_workspace_name(socket: &mut dyn QueryRun) -> Result<String, Error> {
     match socket.query(Request::Workspaces)? {
-        Some( Response::Workspaces(s) ) => Ok::<std::string::String, Error>(s.into_iter().find(|x| x.is_focused).unwrap().name.unwrap_or("".to_string())),
-        None => Ok("".to_string()),
-        _ => Ok("".to_string())
+        Some( Response::Workspaces(s) ) => Ok::<std::string::String, Error>(s.into_iter().find(|x| x.is_focused).ok_or(NiriIPCError::NoFocusedWorkspace)?.name.unwrap_or("".to_string())),
+        other => unexpected(other),
     }
 }
 
-fn get_outputs(socket: &mut niri_ipc::socket::Socket) -> Result<Vec<String>, Error> {
+/// Lists outputs as `(name, label)` pairs sorted by name.
+fn get_outputs(socket: &mut dyn QueryRun, labels: &LabelOptions) -> Result<Vec<(String, String)>, Error> {
+    match socket.query(Request::Outputs)? {
+        Some( Response::Outputs(s) ) => Ok(output_labels(&s, labels)),
+        other => unexpected(other),
+    }
+}
+
+/// Labels `outputs` as `(name, label)` pairs sorted by name. Each label starts with the unique
+/// connector name, so identical monitors get distinct labels as `match_entry` needs.
+fn output_labels(outputs: &HashMap<String, niri_ipc::Output>, labels: &LabelOptions) -> Vec<(String, String)> {
+    // niri sends the outputs as a map without a meaningful order
+    let mut outputs: Vec<(String, String)> = outputs
+        .values()
+        .map(|x| {
+            let mut label = format!("{}: {} {} {}", x.name, x.make, x.model, x.serial.as_deref().unwrap_or(&labels.missing));
+            // disabled outputs have no current mode
+            if let Some(mode) = x.current_mode.and_then(|i| x.modes.get(i)) {
+                label.push_str(&format!(" — {}x{}@{}", mode.width, mode.height, (mode.refresh_rate as f64 / 1000.0).round()));
+            }
+            (x.name.clone(), sanitize_label(label.trim_end()))
+        })
+        .collect();
+    outputs.sort();
+    outputs
+}
+
+/// Selects which windows `get_windows` lists.
+#[derive(Default)]
+struct WindowListOptions {
+    exclude_focused: bool,
+    urgent_only: bool,
+    /// Only list windows on the workspace with this id.
+    workspace: Option<u64>,
+    /// Only list windows on one of these workspaces, e.g. those of one output.
+    workspaces: Option<HashSet<u64>>,
+    /// Leave out windows with one of these app ids.
+    exclude_app_ids: Vec<String>,
+    /// List only the most recently focused window of each app id.
+    dedup_app_ids: bool,
+    /// Sort by focus, most recent first.
+    mru: bool,
+    /// Like `mru`, but with the focused window last.
+    focus_last: bool,
+    /// Only list windows with this app id.
+    app_id: Option<String>,
+    /// Only list windows whose title contains this.
+    title: Option<String>,
+}
+
+fn get_output_info(socket: &mut dyn QueryRun) -> Result<HashMap<String, niri_ipc::Output>, Error> {
     match socket.query(Request::Outputs)? {
-        Some( Response::Outputs(s) ) => Ok::<std::vec::Vec<std::string::String>, Error>(s.values().map(|x| format!("{}: {} {} {}", x.name, x.make, x.model, x.serial.clone().unwrap_or("<unknown>".to_string())).to_string()).collect()),
-        None => Ok(Vec::new()),
-        _ => Ok(Vec::new())
+        Some( Response::Outputs(s) ) => Ok(s),
+        other => unexpected(other),
     }
 }
 
-fn get_windows(socket: &mut niri_ipc::socket::Socket) -> Result<Vec<String>, Error> {
+/// Lists windows as `(id, label)` pairs.
+fn get_windows(socket: &mut dyn QueryRun, labels: &LabelOptions, list: &WindowListOptions) -> Result<Vec<(u64, String)>, Error> {
+    let indices: HashMap<u64, u8> = match labels.workspace_index {
+        true => match socket.query(Request::Workspaces)? {
+            Some(Response::Workspaces(s)) => s.iter().map(|x| (x.id, x.idx)).collect(),
+            other => unexpected(other)?,
+        },
+        false => HashMap::new(),
+    };
     match socket.query(Request::Windows)? {
-        Some( Response::Windows(s) ) => Ok::<std::vec::Vec<std::string::String>, Error>(s.iter().map(|x| format!("{}: {}", x.id, x.title.clone().unwrap_or("Unknown".to_string()))).collect()),
-        None => Ok(Vec::new()),
-        _ => Ok(Vec::new())
+        Some( Response::Windows(s) ) => {
+            let mut windows: Vec<&niri_ipc::Window> = s.iter()
+                .filter(|x| !(list.exclude_focused && x.is_focused) && (x.is_urgent || !list.urgent_only))
+                .filter(|x| list.workspace.is_none() || x.workspace_id == list.workspace)
+                .filter(|x| list.workspaces.as_ref().is_none_or(|ids| x.workspace_id.is_some_and(|id| ids.contains(&id))))
+                .filter(|x| !x.app_id.as_ref().is_some_and(|app_id| list.exclude_app_ids.contains(app_id)))
+                .filter(|x| list.app_id.is_none() || x.app_id == list.app_id)
+                .filter(|x| list.title.as_ref().is_none_or(|title| x.title.as_ref().is_some_and(|t| t.contains(title.as_str()))))
+                .collect();
+            if list.dedup_app_ids {
+                // keep the most recently focused window of each app, windows without app id stay
+                let mut latest: HashMap<&str, &niri_ipc::Window> = HashMap::new();
+                for x in windows.iter().filter(|x| x.app_id.is_some()) {
+                    let app_id = x.app_id.as_deref().unwrap();
+                    let focused = |w: &niri_ipc::Window| w.focus_timestamp.map(|t| (t.secs, t.nanos));
+                    if latest.get(app_id).is_none_or(|other| focused(x) > focused(other)) {
+                        latest.insert(app_id, x);
+                    }
+                }
+                windows.retain(|x| x.app_id.as_deref().is_none_or(|app_id| latest[app_id].id == x.id));
+            }
+            if list.mru || list.focus_last {
+                // never focused windows go after the others
+                windows.sort_by_key(|x| (list.focus_last && x.is_focused, std::cmp::Reverse(x.focus_timestamp.map(|t| (t.secs, t.nanos)))));
+            }
+            let windows = windows
+                .into_iter()
+                .map(|x| {
+                    let title = x.title.as_deref().unwrap_or(&labels.missing);
+                    let title = match labels.keep_newlines {
+                        true => title.split('\n').map(sanitize_label).collect::<Vec<_>>().join("\n"),
+                        false => sanitize_label(title),
+                    };
+                    let mut label = format!("{}: {}", x.id, truncate(&title, labels.max_title_len));
+                    if let (true, Some(app_id)) = (labels.include_app_id, &x.app_id) {
+                        // filters keep matching the full app id
+                        let app_id = match labels.trim_app_id {
+                            true => app_id.rsplit('.').next().unwrap_or(app_id),
+                            false => app_id,
+                        };
+                        label.push_str(&format!(" ({})", sanitize_label(app_id)));
+                    }
+                    if let (true, Some(pid)) = (labels.include_pid, x.pid) {
+                        label.push_str(&format!(" (pid {pid})"));
+                    }
+                    if let Some(idx) = x.workspace_id.and_then(|id| indices.get(&id)) {
+                        label.push_str(&format!(" [ws {idx}]"));
+                    }
+                    match (&labels.icons, &x.app_id) {
+                        (Some(icons), Some(app_id)) => (x.id, format!("{label}\0icon\x1f{}", icons.lookup(app_id))),
+                        _ => (x.id, label),
+                    }
+                })
+                .collect();
+            Ok::<std::vec::Vec<(u64, std::string::String)>, Error>(windows)
+        },
+        other => unexpected(other),
+    }
+}
+
+/// Selects which workspaces `get_workspaces` lists.
+#[derive(Default)]
+struct WorkspaceListOptions {
+    non_empty: bool,
+    occupied_first: bool,
+    index_prefix: bool,
+    /// Only list the workspaces of the focused output, index labels then leave out the output.
+    focused_output: bool,
+    sort: WorkspaceSort,
+}
+
+#[derive(Default)]
+enum WorkspaceSort {
+    #[default]
+    Idx,
+    Id,
+    Name,
+}
+
+impl WorkspaceSort {
+    fn from_name(name: Option<&str>) -> Result<WorkspaceSort, Error> {
+        match name {
+            None | Some("idx") => Ok(WorkspaceSort::Idx),
+            Some("id") => Ok(WorkspaceSort::Id),
+            Some("name") => Ok(WorkspaceSort::Name),
+            Some(other) => Err(format_err!("Invalid value '{}' for sort", other)),
+        }
     }
 }
 
-fn get_workspaces(socket: &mut niri_ipc::socket::Socket) -> Result<Vec<String>, Error> {
+fn workspace_sort_arg() -> Arg<'static, 'static> {
+    Arg::with_name("sort")
+        .long("sort")
+        .takes_value(true)
+        .possible_values(&["idx", "id", "name"])
+        .help("Order of the listed workspaces [default: idx]")
+}
+
+/// Lists workspaces as `(id, label)` pairs in the requested order.
+fn get_workspaces(socket: &mut dyn QueryRun, labels: &LabelOptions, list: &WorkspaceListOptions) -> Result<Vec<(u64, String)>, Error> {
+    let occupied = match list.non_empty || list.occupied_first {
+        true => Some(get_occupied_workspaces(socket)?),
+        false => None,
+    };
     match socket.query(Request::Workspaces)? {
         Some( Response::Workspaces(s) ) => {
             let mut si = s.clone();
-            si.sort_by(|a, b| a.idx.cmp(&b.idx));
-            let spaces = si.iter().map(|x| format!("{}: {} ({})", x.id, x.name.clone().unwrap_or("<unnamed>".to_string()), x.idx)).collect();
-            Ok::<std::vec::Vec<std::string::String>, Error>(spaces)
+            if let (true, Some(occupied)) = (list.non_empty, &occupied) {
+                si.retain(|x| occupied.contains(&x.id));
+            }
+            if list.focused_output {
+                let output = s.iter().find(|x| x.is_focused).ok_or(NiriIPCError::NoFocusedWorkspace)?.output.clone();
+                si.retain(|x| x.output == output);
+            }
+            si.sort_by_key(|a| a.idx);
+            match list.sort {
+                WorkspaceSort::Idx => {}
+                WorkspaceSort::Id => si.sort_by_key(|a| a.id),
+                // unnamed workspaces go last, in index order
+                WorkspaceSort::Name => si.sort_by(|a, b| (a.name.is_none(), &a.name).cmp(&(b.name.is_none(), &b.name))),
+            }
+            if let (true, Some(occupied)) = (list.occupied_first, &occupied) {
+                si.sort_by_key(|x| !occupied.contains(&x.id));
+            }
+            let spaces = si.iter().map(|x| {
+                let name = sanitize_label(x.name.as_deref().unwrap_or(&labels.missing));
+                let label = match (list.index_prefix, list.focused_output) {
+                    (true, true) => format!("{}: {}", x.idx, name),
+                    (true, false) => format!("{}: {} ({})", x.idx, name, x.output.as_deref().unwrap_or(&labels.missing)),
+                    (false, _) => format!("{}: {} ({})", x.id, name, x.idx),
+                };
+                (x.id, label)
+            }).collect();
+            Ok::<std::vec::Vec<(u64, std::string::String)>, Error>(spaces)
         },
-        None => Ok(Vec::new()),
-        _ => Ok(Vec::new())
+        other => unexpected(other),
     }
 }
 
-fn get_current_workspace(socket: &mut niri_ipc::socket::Socket) -> Result<u64, Error> {
+/// Finds the id of the workspace named `reference`. Failing that, a number is taken as the index
+/// of a workspace on the focused output, like niri does, and then as a workspace id.
+fn resolve_workspace(socket: &mut dyn QueryRun, reference: &str) -> Result<u64, Error> {
+    let workspaces = match socket.query(Request::Workspaces)? {
+        Some(Response::Workspaces(s)) => s,
+        other => unexpected(other)?,
+    };
+    let focused_output = workspaces.iter().find(|x| x.is_focused).and_then(|x| x.output.clone());
+    let number = reference.parse::<u64>().ok();
+    workspaces
+        .iter()
+        .find(|x| x.name.as_deref() == Some(reference))
+        .or_else(|| workspaces.iter().find(|x| Some(u64::from(x.idx)) == number && x.output == focused_output))
+        .or_else(|| workspaces.iter().find(|x| Some(x.id) == number))
+        .map(|x| x.id)
+        .ok_or_else(|| NiriIPCError::NoMatch { what: "workspace".to_string(), input: reference.to_string() }.into())
+}
+
+/// Ids of the workspaces on the same output as workspace `id`, including it.
+fn get_sibling_workspaces(socket: &mut dyn QueryRun, id: u64) -> Result<HashSet<u64>, Error> {
+    let workspaces = match socket.query(Request::Workspaces)? {
+        Some(Response::Workspaces(s)) => s,
+        other => unexpected(other)?,
+    };
+    let output = workspaces.iter().find(|x| x.id == id).and_then(|x| x.output.clone());
+    Ok(workspaces.iter().filter(|x| x.output == output).map(|x| x.id).collect())
+}
+
+/// Ids of the workspaces whose name contains `substring`, unnamed workspaces never match.
+fn get_workspaces_named(socket: &mut dyn QueryRun, substring: &str) -> Result<HashSet<u64>, Error> {
+    let workspaces = match socket.query(Request::Workspaces)? {
+        Some(Response::Workspaces(s)) => s,
+        other => unexpected(other)?,
+    };
+    Ok(workspaces
+        .iter()
+        .filter(|x| x.name.as_deref().is_some_and(|name| name.contains(substring)))
+        .map(|x| x.id)
+        .collect())
+}
+
+fn get_focused_window(socket: &mut dyn QueryRun) -> Result<Option<niri_ipc::Window>, Error> {
+    match socket.query(Request::FocusedWindow)? {
+        Some(Response::FocusedWindow(window)) => Ok(window),
+        other => unexpected(other),
+    }
+}
+
+/// Id of the workspace `reference` points to, indices count on the focused output.
+fn target_workspace_id(socket: &mut dyn QueryRun, reference: &niri_ipc::WorkspaceReferenceArg) -> Result<Option<u64>, Error> {
+    let idx = match reference {
+        niri_ipc::WorkspaceReferenceArg::Id(id) => return Ok(Some(*id)),
+        niri_ipc::WorkspaceReferenceArg::Index(idx) => *idx,
+        niri_ipc::WorkspaceReferenceArg::Name(_) => return Ok(None),
+    };
+    let workspaces = match socket.query(Request::Workspaces)? {
+        Some(Response::Workspaces(s)) => s,
+        other => unexpected(other)?,
+    };
+    let focused_output = workspaces.iter().find(|x| x.is_focused).and_then(|x| x.output.clone());
+    Ok(workspaces.iter().find(|x| x.idx == idx && x.output == focused_output).map(|x| x.id))
+}
+
+/// Ids of all workspaces holding at least one window.
+fn get_occupied_workspaces(socket: &mut dyn QueryRun) -> Result<HashSet<u64>, Error> {
+    match socket.query(Request::Windows)? {
+        Some( Response::Windows(s) ) => Ok(s.iter().filter_map(|x| x.workspace_id).collect()),
+        other => unexpected(other),
+    }
+}
+
+fn get_current_workspace(socket: &mut dyn QueryRun) -> Result<u64, Error> {
     match socket.query(Request::Workspaces)? {
-        Some( Response::Workspaces(s) ) => Ok::<u64, Error>(s.into_iter().find(|x| x.is_focused).unwrap().id),
-        None => Ok(0),
-        _ => Ok(0),
+        Some( Response::Workspaces(s) ) => Ok::<u64, Error>(s.into_iter().find(|x| x.is_focused).ok_or(NiriIPCError::NoFocusedWorkspace)?.id),
+        other => unexpected(other),
+    }
+}
+
+/// Splits a comma separated config value.
+fn split_list(value: &str) -> Vec<String> {
+    value.split(',').map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect()
+}
+
+/// Parses `key=color` pairs separated by commas, the colors are passed on as given.
+fn parse_colors(value: &str) -> Result<Vec<(String, String)>, Error> {
+    split_list(value)
+        .iter()
+        .map(|pair| {
+            let (key, color) = pair.split_once('=').ok_or_else(|| format_err!("Invalid color '{}', expected key=color", pair))?;
+            let key = key.trim();
+            if !picker::COLOR_KEYS.contains(&key) {
+                return Err(format_err!("Unknown color '{}', expected one of {}", key, picker::COLOR_KEYS.join(", ")));
+            }
+            Ok((key.to_string(), color.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Extracts the number leading a window or workspace label, `what` names it for errors.
+fn label_id(label: &str, what: &str) -> Result<u64, Error> {
+    let id = label.split(":").next().expect("Can't split out id").trim();
+    id.parse::<u64>()
+        .map_err(|_| NiriIPCError::ParseId { what: what.to_string(), selection: label.to_string() }.into())
+}
+
+/// Finds the key of the entry the picker returned.
+///
+/// Entries are handed around as `(key, label)` pairs and the key is never parsed back out of the
+/// label; instead the selected line is compared with the labels. That way titles and names
+/// containing `": "` or starting with digits can't be mistaken for the key, and no picker has to
+/// hide a key column (dmenu, bemenu and tofi can't). Anything after a `\0` in a label is picker
+/// metadata such as a fuzzel icon and is not printed on selection. `None` means the user typed
+/// text matching no entry.
+fn match_entry<'e, K>(entries: &'e [(K, String)], selection: &str) -> Option<&'e K> {
+    entries
+        .iter()
+        .find(|(_, label)| label.split('\0').next() == Some(selection))
+        .map(|(key, _)| key)
+}
+
+/// The connector name of `outputs` that `name` refers to, ignoring case. niri silently ignores
+/// actions on names it doesn't know, so anything else is an error listing the valid names.
+fn canonical_output(outputs: &[(String, String)], name: &str) -> Result<String, Error> {
+    match outputs.iter().find(|(output, _)| output.eq_ignore_ascii_case(name)) {
+        Some((output, _)) => Ok(output.clone()),
+        None => {
+            let names: Vec<&str> = outputs.iter().map(|(output, _)| output.as_str()).collect();
+            Err(format_err!("Output {} is not connected, expected one of {}", name, names.join(", ")))
+        }
     }
 }
 
-fn fuzzel_get_selection_id(input: &[String]) -> String {
-    let fuzzel_out = fuzzel_run(input);
-    fuzzel_out
-        .split(":")
-        .next()
-        .expect("Can't split out id")
-        .to_string()
+/// Extracts the connector name from typed text in the format of the `get_outputs` labels.
+fn parse_output_name(selection: &str) -> Option<String> {
+    let name = selection.split(":").next()?.trim();
+    match name.is_empty() {
+        true => None,
+        false => Some(name.to_string()),
+    }
+}
+
+/// Parses typed output scale, `auto` or a positive number. Infinity is rejected, it would be sent
+/// to niri as `null`.
+fn parse_scale(input: &str) -> Result<niri_ipc::ScaleToSet, Error> {
+    match input.trim() {
+        "auto" => Ok(niri_ipc::ScaleToSet::Automatic),
+        value => match value.parse::<f64>() {
+            Ok(scale) if scale.is_finite() && scale > 0.0 => Ok(niri_ipc::ScaleToSet::Specific(scale)),
+            _ => Err(format_err!("Invalid scale '{}', expected a positive number or auto", value)),
+        },
+    }
 }
 
 #[derive(Debug)]
@@ -248,34 +2081,363 @@ struct IDorEntry {
     entry: String,
 }
 
-fn fuzzel_get_selection_id_or_entry(input: &[String]) -> IDorEntry {
-    let fuzzel_out = fuzzel_run(input);
-    let mut entry = IDorEntry {
-        id: None,
-        entry: fuzzel_out.strip_suffix('\n').expect("Failed to strip newline").to_string()
-    };
-    match fuzzel_out.contains(":") {
-        true => {
-            entry.id = Some(fuzzel_out.split(":") .next() .expect("Can't split out id").parse::<u64>().expect("Failed to convert ID to u64"));
-            entry
-        }
-        false => entry
-    }
-}
-
-fn fuzzel_run(input: &[String]) -> String {
-    let mut child = Command::new("fuzzel")
-        .arg("--dmenu")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()
-        .expect("Can't open fuzzel");
-    {
-        let stdin = child.stdin.as_mut().expect("failed to get stdin");
-        stdin
-            .write_all(input.join("\n").as_bytes())
-            .expect("failed to write to fuzzel");
-    }
-    let output = child.wait_with_output().expect("failed to wait on child");
-    String::from_utf8(output.stdout).expect("Can't read output")
+fn is_number(value: String) -> Result<(), String> {
+    value.parse::<usize>().map(|_| ()).map_err(|_| format!("'{value}' is not a number"))
+}
+
+fn parse_setting<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, Error> {
+    value.parse::<T>().map_err(|_| format_err!("Invalid value '{}' for {}", value, name))
+}
+
+/// Builds the picker prompt, with `count` set it reads e.g. `"window (42)> "`, using the
+/// configured prompt in place of `kind` if there is one.
+fn picker_prompt(prompt: Option<&str>, kind: &str, count: Option<usize>) -> Option<String> {
+    match count {
+        Some(count) => {
+            let base = prompt.map(|p| p.trim_end_matches([' ', '>', ':'])).unwrap_or(kind);
+            Some(format!("{base} ({count})> "))
+        }
+        None => prompt.map(|p| p.to_string()),
+    }
+}
+
+/// Reads `label<TAB>command` lines from stdin as `(command, label)` pairs, a line without a tab
+/// is used as both.
+fn read_stdin_items() -> Result<Vec<(String, String)>, Error> {
+    let mut items = Vec::new();
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (label, command) = line.split_once('\t').unwrap_or((&line, &line));
+        items.push((command.to_string(), sanitize_label(label)));
+    }
+    Ok(items)
+}
+
+fn menu(picker: &dyn Picker, prompt: Option<&str>, prompt_count: bool) -> Result<(), Error> {
+    let input = std::io::stdin().lock().lines().collect::<Result<Vec<_>, _>>()?;
+    let prompt = picker_prompt(prompt, "item", prompt_count.then_some(input.len()));
+    match picker.pick(&input, prompt.as_deref())? {
+        Some(selection) => {
+            println!("{selection}");
+            Ok(())
+        }
+        // dmenu reports a cancelled selection through the exit code
+        None => Err(NiriIPCError::PickerCancelled)?,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_label_keeps_one_line() {
+        let label = sanitize_label("foot: ~/src\nsecond\tline\r");
+        assert_eq!(label, "foot: ~/src second line ");
+        assert_eq!(label.lines().count(), 1);
+    }
+
+    #[test]
+    fn match_entry_resolves_titles_starting_with_digits() {
+        let entries = vec![
+            (7, "42 unread - Chat".to_string()),
+            (42, "Inbox".to_string()),
+            (9, "9: foot\0icon\x1ffoot".to_string()),
+        ];
+        assert_eq!(match_entry(&entries, "42 unread - Chat"), Some(&7));
+        assert_eq!(match_entry(&entries, "Inbox"), Some(&42));
+        // the icon suffix is not printed by the picker
+        assert_eq!(match_entry(&entries, "9: foot"), Some(&9));
+        assert_eq!(match_entry(&entries, "42"), None);
+    }
+
+    fn output(name: &str) -> niri_ipc::Output {
+        niri_ipc::Output {
+            name: name.to_string(),
+            make: "Dell".to_string(),
+            model: "U2720Q".to_string(),
+            serial: None,
+            physical_size: None,
+            modes: vec![],
+            current_mode: None,
+            is_custom_mode: false,
+            vrr_supported: false,
+            vrr_enabled: false,
+            logical: None,
+        }
+    }
+
+    fn label_options() -> LabelOptions {
+        LabelOptions {
+            max_title_len: None,
+            include_pid: false,
+            include_app_id: false,
+            trim_app_id: false,
+            workspace_index: false,
+            icons: None,
+            keep_newlines: false,
+            missing: "<unknown>".to_string(),
+        }
+    }
+
+    #[test]
+    fn output_labels_tell_identical_monitors_apart() {
+        let labels = label_options();
+        let outputs: HashMap<String, niri_ipc::Output> =
+            ["HDMI-A-1", "DP-1"].into_iter().map(|name| (name.to_string(), output(name))).collect();
+        let entries = output_labels(&outputs, &labels);
+        assert_eq!(
+            entries,
+            [
+                ("DP-1".to_string(), "DP-1: Dell U2720Q <unknown>".to_string()),
+                ("HDMI-A-1".to_string(), "HDMI-A-1: Dell U2720Q <unknown>".to_string()),
+            ]
+        );
+        for (name, label) in &entries {
+            assert_eq!(match_entry(&entries, label), Some(name));
+        }
+    }
+
+    /// Answers requests with scripted replies in order and records what was sent.
+    struct FakeSocket {
+        replies: std::collections::VecDeque<niri_ipc::Reply>,
+        requests: Vec<Request>,
+    }
+
+    impl FakeSocket {
+        fn new(replies: Vec<niri_ipc::Reply>) -> FakeSocket {
+            FakeSocket { replies: replies.into(), requests: Vec::new() }
+        }
+    }
+
+    impl QueryRun for FakeSocket {
+        fn send(&mut self, request: Request) -> Result<niri_ipc::Reply, Error> {
+            self.requests.push(request);
+            self.replies.pop_front().ok_or_else(|| format_err!("No reply scripted"))
+        }
+    }
+
+    /// Returns the same line for every pick and prompt.
+    struct FakePicker(Option<String>);
+
+    impl Picker for FakePicker {
+        fn pick(&self, _input: &[String], _prompt: Option<&str>) -> Result<Option<String>, Error> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn state<'a>(socket: &'a mut FakeSocket, selection: Option<&str>) -> ApplicationState<'a> {
+        ApplicationState {
+            socket,
+            confdir: Path::new("/nonexistent"),
+            picker: Box::new(FakePicker(selection.map(|s| s.to_string()))),
+            prompt: None,
+            prompt_count: false,
+            placeholder: None,
+            exit_on_empty: true,
+            no_picker_on_single: false,
+            select_index: None,
+            keep_going: false,
+            labels: label_options(),
+            config: Config::default(),
+            selected: RefCell::new(None),
+            history: None,
+        }
+    }
+
+    #[test]
+    fn failed_focus_skips_naming_the_workspace() {
+        let mut socket = FakeSocket::new(vec![Err("no such workspace".to_string())]);
+        let err = state(&mut socket, None).focus_and_name_workspace(5, "mail".to_string()).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(NiriIPCError::ActionFailed { .. })));
+        assert!(matches!(
+            socket.requests.as_slice(),
+            [Request::Action(Action::FocusWorkspace { reference: niri_ipc::WorkspaceReferenceArg::Id(5) })]
+        ));
+    }
+
+    #[test]
+    fn wrapped_actions_cover_picker_and_direct_subcommands() {
+        let pairs = wrapped_actions();
+        for (name, variant) in [
+            ("focus-container", "SpawnSh"),
+            ("name-current-workspace", "UnsetWorkspaceName"),
+            ("move-column-to-monitor", "MoveColumnToMonitor"),
+            ("focus-monitor-left", "FocusMonitorLeft"),
+        ] {
+            assert!(pairs.iter().any(|(n, v)| *n == name && v == variant), "{name} {variant}");
+        }
+        assert_eq!(pairs.iter().filter(|(name, _)| *name == "focus-workspace").count(), 2);
+    }
+
+    fn window(id: u64, workspace_id: u64, is_focused: bool) -> niri_ipc::Window {
+        niri_ipc::Window {
+            id,
+            title: Some("foot".to_string()),
+            app_id: Some("foot".to_string()),
+            pid: None,
+            workspace_id: Some(workspace_id),
+            is_focused,
+            is_floating: false,
+            is_urgent: false,
+            layout: niri_ipc::WindowLayout {
+                pos_in_scrolling_layout: None,
+                tile_size: (0.0, 0.0),
+                window_size: (0, 0),
+                tile_pos_in_workspace_view: None,
+                window_offset_in_tile: (0.0, 0.0),
+            },
+            focus_timestamp: None,
+        }
+    }
+
+    fn workspace(id: u64, idx: u8, is_focused: bool) -> niri_ipc::Workspace {
+        niri_ipc::Workspace {
+            id,
+            idx,
+            name: None,
+            output: Some("DP-1".to_string()),
+            is_urgent: false,
+            is_active: is_focused,
+            is_focused,
+            active_window_id: None,
+        }
+    }
+
+    /// Matches of `niri-action <subcommand> --<flag>...`, options not defined here read as unset.
+    fn subcommand_matches(subcommand: &'static str, flags: &[&'static str]) -> ArgMatches<'static> {
+        let args = flags.iter().map(|flag| Arg::with_name(flag).long(flag));
+        let given = flags.iter().map(|flag| format!("--{flag}"));
+        App::new("niri-action")
+            .subcommand(SubCommand::with_name(subcommand).args(&args.collect::<Vec<_>>()))
+            .get_matches_from(["niri-action".to_string(), subcommand.to_string()].into_iter().chain(given))
+    }
+
+    #[test]
+    fn steal_container_moves_the_window_once() {
+        let mut socket = FakeSocket::new(vec![
+            Ok(Response::Windows(vec![window(7, 1, false)])),
+            Ok(Response::Workspaces(vec![workspace(1, 1, false), workspace(2, 2, true)])),
+            Ok(Response::Handled),
+        ]);
+        let matches = subcommand_matches("steal-container", &["focus"]);
+        state(&mut socket, Some("7: foot")).steal_container_by_id(&matches).unwrap();
+        let moves: Vec<&Request> = socket.requests.iter().filter(|r| matches!(r, Request::Action(_))).collect();
+        assert!(matches!(
+            moves.as_slice(),
+            [Request::Action(Action::MoveWindowToWorkspace {
+                window_id: Some(7),
+                reference: niri_ipc::WorkspaceReferenceArg::Id(2),
+                focus: true,
+            })]
+        ));
+    }
+
+    #[test]
+    fn move_to_workspace_keep_focus_refocuses_the_moved_window() {
+        let mut socket = FakeSocket::new(vec![
+            Ok(Response::Workspaces(vec![workspace(1, 1, true), workspace(2, 2, false)])),
+            Ok(Response::FocusedWindow(Some(window(7, 1, true)))),
+            Ok(Response::Handled),
+            Ok(Response::Handled),
+        ]);
+        let matches = subcommand_matches("move-to-workspace", &["keep-focus"]);
+        state(&mut socket, Some("2: <unknown> (2)")).move_to_workspace_by_name(&matches).unwrap();
+        assert!(matches!(
+            socket.requests.as_slice(),
+            [
+                Request::Workspaces,
+                Request::FocusedWindow,
+                Request::Action(Action::MoveWindowToWorkspace {
+                    window_id: None,
+                    reference: niri_ipc::WorkspaceReferenceArg::Id(2),
+                    focus: false,
+                }),
+                Request::Action(Action::FocusWindow { id: 7 }),
+            ]
+        ));
+    }
+
+    #[test]
+    fn output_labels_of_no_outputs_are_empty() {
+        assert!(output_labels(&HashMap::new(), &label_options()).is_empty());
+    }
+
+    #[test]
+    fn move_workspace_to_output_without_outputs_sends_nothing() {
+        let mut socket = FakeSocket::new(vec![Ok(Response::Outputs(HashMap::new()))]);
+        // cancelling would fail, so reaching Ok means the picker wasn't shown
+        state(&mut socket, None).move_workspace_to_output().unwrap();
+        assert!(matches!(socket.requests.as_slice(), [Request::Outputs]));
+    }
+
+    #[test]
+    fn quick_workspace_labels_hold_only_the_index_digits() {
+        let mut other = workspace(3, 1, false);
+        other.output = Some("HDMI-A-1".to_string());
+        let mut named = workspace(2, 2, false);
+        named.name = Some("mail".to_string());
+        let mut socket = FakeSocket::new(vec![Ok(Response::Workspaces(vec![workspace(1, 1, true), named, other]))]);
+        let list = WorkspaceListOptions { index_prefix: true, focused_output: true, ..Default::default() };
+        let entries = get_workspaces(&mut socket, &label_options(), &list).unwrap();
+        assert_eq!(entries, [(1, "1: <unknown>".to_string()), (2, "2: mail".to_string())]);
+    }
+
+    #[test]
+    fn keep_going_names_each_failed_window() {
+        let mut socket = FakeSocket::new(vec![
+            Ok(Response::Windows(vec![window(7, 1, false), window(8, 1, false), window(9, 1, false)])),
+            Err("busy".to_string()),
+            // the failed window still exists
+            Ok(Response::Windows(vec![window(7, 1, false)])),
+            Ok(Response::Handled),
+            Err("busy".to_string()),
+            Ok(Response::Windows(vec![window(9, 1, false)])),
+        ]);
+        let mut state = state(&mut socket, None);
+        state.keep_going = true;
+        let matches = App::new("niri-action")
+            .subcommand(SubCommand::with_name("close-window").args(&[Arg::with_name("all").long("all"), Arg::with_name("app-id").long("app-id").takes_value(true)]))
+            .get_matches_from(["niri-action", "close-window", "--all", "--app-id", "foot"]);
+        let err = state.close_window(&matches).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "2 of 3 actions failed: 7: niri refused the action: busy; 9: niri refused the action: busy"
+        );
+    }
+
+    #[test]
+    fn parse_scale_rejects_what_niri_cannot_take() {
+        assert!(matches!(parse_scale(" auto\n"), Ok(niri_ipc::ScaleToSet::Automatic)));
+        assert!(matches!(parse_scale("1.5"), Ok(niri_ipc::ScaleToSet::Specific(scale)) if scale == 1.5));
+        for input in ["0", "-1", "inf", "infinity", "NaN", "big"] {
+            assert!(parse_scale(input).is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn failed_warp_command_is_an_error() {
+        let mut output = output("DP-1");
+        output.logical = Some(niri_ipc::LogicalOutput { x: 0, y: 0, width: 1920, height: 1080, scale: 1.0, transform: niri_ipc::Transform::Normal });
+        let mut socket = FakeSocket::new(vec![Ok(Response::FocusedOutput(Some(output)))]);
+        let matches = App::new("niri-action")
+            .arg(Arg::with_name("warp-command").long("warp-command").takes_value(true))
+            .get_matches_from(["niri-action", "--warp-command", "false"]);
+        let err = state(&mut socket, None).warp_to_focused_output(&matches).unwrap_err();
+        assert!(err.to_string().starts_with("false failed"), "{err}");
+    }
+
+    #[test]
+    fn multi_line_titles_keep_the_list_length() {
+        let mut multi = window(8, 1, false);
+        multi.title = Some("vim\n~/src/main.rs\r\n".to_string());
+        let mut socket = FakeSocket::new(vec![Ok(Response::Windows(vec![window(7, 1, false), multi, window(9, 1, false)]))]);
+        let entries = get_windows(&mut socket, &label_options(), &WindowListOptions::default()).unwrap();
+        assert_eq!(entries.len(), 3);
+        let labels: Vec<String> = entries.into_iter().map(|(_, label)| label).collect();
+        assert_eq!(labels.join("\n").lines().count(), 3);
+    }
 }
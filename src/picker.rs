@@ -0,0 +1,509 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use failure::Error;
+use unicode_width::UnicodeWidthStr;
+
+use crate::NiriIPCError;
+
+/// Names accepted by `--picker`.
+pub const NAMES: &[&str] = &["fuzzel", "bemenu", "tofi", "dmenu", "fzf"];
+
+/// Keys accepted by `--colors`, named after fuzzel's `--<key>-color` options.
+pub const COLOR_KEYS: &[&str] = &["background", "text", "prompt", "input", "match", "selection", "selection-text", "selection-match", "border"];
+
+/// Values accepted by `--match`, named after fuzzel's match modes.
+pub const MATCH_MODES: &[&str] = &["fuzzy", "exact", "fzf"];
+
+/// Whether `binary` can be found in one of the `$PATH` directories.
+pub fn on_path(binary: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).any(|dir| dir.join(binary).is_file()))
+        .unwrap_or(false)
+}
+
+/// A menu program presenting a list of lines to choose from.
+pub trait Picker {
+    /// Shows `input` and returns the chosen (or typed) line, `None` if the user cancelled.
+    fn pick(&self, input: &[String], prompt: Option<&str>) -> Result<Option<String>, Error>;
+
+    /// Asks for free text, `placeholder` is shown in the empty input field by pickers supporting
+    /// it and ignored by the others. The same goes for hiding the input with `Style::hidden`.
+    fn prompt(&self, prompt: &str, _placeholder: Option<&str>) -> Result<Option<String>, Error> {
+        self.pick(&[], Some(prompt))
+    }
+
+    /// Whether entries may carry an icon as `label\0icon\x1f<name>`, the picker prints only the
+    /// label when it is selected.
+    fn icons(&self) -> bool {
+        false
+    }
+
+    /// Whether entries are read NUL separated, so they may contain newlines.
+    fn null_input(&self) -> bool {
+        false
+    }
+}
+
+/// Appearance settings common to all pickers, each backend maps what it supports.
+#[derive(Default)]
+pub struct Style {
+    /// Menu width in characters.
+    pub width: Option<usize>,
+    /// Number of visible entries.
+    pub lines: Option<usize>,
+    pub font: Option<String>,
+    /// `(key, color)` pairs, the keys are from `COLOR_KEYS`.
+    pub colors: Vec<(String, String)>,
+    /// How typed text matches entries, one of `MATCH_MODES`.
+    pub match_mode: Option<String>,
+    /// Show the number of matching entries while typing.
+    pub counter: bool,
+    /// Size the menu to the longest entry unless `width` is set.
+    pub auto_width: bool,
+    /// Text the filter starts with.
+    pub initial: Option<String>,
+    /// Confirm as soon as a single entry matches the typed text.
+    pub auto_select: bool,
+    /// Show application icons next to windows.
+    pub icons: bool,
+    /// Icon theme used to look up the icons.
+    pub icon_theme: Option<String>,
+    /// Ignore the picker's own configuration, so it can't switch off dmenu mode or hide entries.
+    pub no_config: bool,
+    /// Separate entries by NUL instead of newlines.
+    pub null_input: bool,
+    /// Don't echo the text typed into free-text prompts.
+    pub hidden: bool,
+    /// Start the picker again if it dies while printing the selection.
+    pub retry: bool,
+}
+
+/// Creates the named picker, `terminal` is the command used to host terminal based pickers.
+pub fn from_name(name: &str, style: Style, terminal: Option<&str>) -> Option<Box<dyn Picker>> {
+    // only fuzzel understands the icon suffix of the entries
+    if style.icons && name != "fuzzel" && NAMES.contains(&name) {
+        unsupported(name, "icons");
+    }
+    // the dmenu likes are line based, titles keep their newlines replaced
+    if style.null_input && name != "fzf" && NAMES.contains(&name) {
+        unsupported(name, "null-input");
+    }
+    if style.auto_width && name != "fuzzel" && NAMES.contains(&name) {
+        unsupported(name, "auto-width");
+    }
+    if style.auto_select && !["fuzzel", "tofi"].contains(&name) && NAMES.contains(&name) {
+        unsupported(name, "auto-select");
+    }
+    // tofi and dmenu start with an empty filter
+    if style.initial.is_some() && ["tofi", "dmenu"].contains(&name) {
+        unsupported(name, "initial");
+    }
+    // fzf always shows the counter
+    if style.counter && !["fuzzel", "fzf"].contains(&name) && NAMES.contains(&name) {
+        unsupported(name, "counter");
+    }
+    // bemenu and dmenu only match substrings, tofi has no fzf algorithm, fzf is fuzzy by default
+    let ignored = match (name, style.match_mode.as_deref()) {
+        (_, None) | ("fuzzel" | "fzf", _) => false,
+        ("tofi", Some(mode)) => mode == "fzf",
+        (_, Some(mode)) => mode != "exact",
+    };
+    if ignored && NAMES.contains(&name) {
+        unsupported(name, &format!("match={}", style.match_mode.as_deref().unwrap_or_default()));
+    }
+    match name {
+        "fuzzel" => Some(Box::new(Fuzzel { style })),
+        "bemenu" => {
+            // bemenu only knows the width as a fraction of the screen
+            if style.width.is_some() {
+                unsupported(name, "width");
+            }
+            Some(Box::new(Bemenu { style }))
+        }
+        "tofi" => {
+            // tofi takes the width in pixels or percent
+            if style.width.is_some() {
+                unsupported(name, "width");
+            }
+            // tofi's color options don't line up with fuzzel's
+            if !style.colors.is_empty() {
+                unsupported(name, "colors");
+            }
+            Some(Box::new(Tofi { style }))
+        }
+        "dmenu" => {
+            // plain dmenu always spans the screen
+            if style.width.is_some() {
+                unsupported(name, "width");
+            }
+            // dmenu is configured at compile time only
+            if style.no_config {
+                unsupported(name, "picker-no-config");
+            }
+            Some(Box::new(Dmenu { style }))
+        }
+        "fzf" => {
+            // the terminal decides about size and font
+            for (option, set) in [
+                ("width", style.width.is_some()),
+                ("lines", style.lines.is_some()),
+                ("font", style.font.is_some()),
+                ("colors", !style.colors.is_empty()),
+            ] {
+                if set {
+                    unsupported(name, option);
+                }
+            }
+            let terminal = terminal
+                .map(|t| t.to_string())
+                .or_else(|| std::env::var("TERMINAL").ok())
+                .unwrap_or("foot".to_string());
+            let exact = style.match_mode.as_deref() == Some("exact");
+            Some(Box::new(Fzf { terminal, no_config: style.no_config, null_input: style.null_input, exact, initial: style.initial }))
+        }
+        _ => None,
+    }
+}
+
+fn unsupported(picker: &str, option: &str) {
+    crate::warn(&format!("{picker} does not support --{option}, ignoring it"));
+}
+
+/// Adds the colors of `style` using the `(key, option)` table of a picker, keys missing from the
+/// table are warned about.
+fn color_args(command: &mut Command, picker: &str, style: &Style, options: &[(&str, &str)]) {
+    for (key, color) in &style.colors {
+        let mut found = false;
+        for (_, option) in options.iter().filter(|(k, _)| k == key) {
+            command.arg(option).arg(color);
+            found = true;
+        }
+        if !found {
+            crate::warn(&format!("{picker} has no {key} color, ignoring it"));
+        }
+    }
+}
+
+pub struct Fuzzel {
+    style: Style,
+}
+
+impl Fuzzel {
+    fn command(&self, prompt: Option<&str>) -> Command {
+        let mut command = Command::new("fuzzel");
+        command.arg("--dmenu");
+        if self.style.no_config {
+            command.arg("--config=/dev/null");
+        }
+        if let Some(prompt) = prompt {
+            command.arg(format!("--prompt={prompt}"));
+        }
+        if let Some(width) = self.style.width {
+            command.arg(format!("--width={width}"));
+        }
+        if let Some(lines) = self.style.lines {
+            command.arg(format!("--lines={lines}"));
+        }
+        if let Some(font) = &self.style.font {
+            command.arg(format!("--font={font}"));
+        }
+        if let (true, Some(theme)) = (self.style.icons, &self.style.icon_theme) {
+            command.arg(format!("--icon-theme={theme}"));
+        }
+        for (key, color) in &self.style.colors {
+            command.arg(format!("--{key}-color={color}"));
+        }
+        if let Some(mode) = &self.style.match_mode {
+            command.arg(format!("--match-mode={mode}"));
+        }
+        if self.style.counter {
+            command.arg("--counter");
+        }
+        if let Some(initial) = &self.style.initial {
+            command.arg(format!("--search={initial}"));
+        }
+        if self.style.auto_select {
+            command.arg("--auto-select");
+        }
+        command
+    }
+}
+
+/// Menu width in characters fitting `prompt` and the widest of `input`, with room for the
+/// padding and icons, but never wider than 150 characters.
+fn auto_width(input: &[String], prompt: Option<&str>, icons: bool) -> usize {
+    let widest = input
+        .iter()
+        .filter_map(|entry| entry.split(['\0', '\n']).next())
+        .chain(prompt)
+        .map(UnicodeWidthStr::width)
+        .max()
+        .unwrap_or(0);
+    let icon = if icons { 3 } else { 0 };
+    (widest + icon + 4).clamp(20, 150)
+}
+
+impl Picker for Fuzzel {
+    fn pick(&self, input: &[String], prompt: Option<&str>) -> Result<Option<String>, Error> {
+        let mut command = self.command(prompt);
+        if self.style.auto_width && self.style.width.is_none() {
+            command.arg(format!("--width={}", auto_width(input, prompt, self.style.icons)));
+        }
+        run_dmenu(command, input, self.style.retry)
+    }
+
+    fn prompt(&self, prompt: &str, placeholder: Option<&str>) -> Result<Option<String>, Error> {
+        let mut command = self.command(Some(prompt));
+        if let Some(placeholder) = placeholder {
+            command.arg(format!("--placeholder={placeholder}"));
+        }
+        if self.style.hidden {
+            command.arg("--password");
+        }
+        run_dmenu(command, &[], self.style.retry)
+    }
+
+    fn icons(&self) -> bool {
+        self.style.icons
+    }
+}
+
+pub struct Bemenu {
+    style: Style,
+}
+
+impl Picker for Bemenu {
+    fn pick(&self, input: &[String], prompt: Option<&str>) -> Result<Option<String>, Error> {
+        let mut command = Command::new("bemenu");
+        // bemenu has no config file, only the environment
+        if self.style.no_config {
+            command.env_remove("BEMENU_OPTS");
+        }
+        if let Some(prompt) = prompt {
+            command.arg("-p").arg(prompt);
+        }
+        if let Some(lines) = self.style.lines {
+            command.arg("-l").arg(lines.to_string());
+        }
+        if let Some(font) = &self.style.font {
+            command.arg("--fn").arg(font);
+        }
+        if let Some(initial) = &self.style.initial {
+            command.arg("--filter").arg(initial);
+        }
+        color_args(
+            &mut command,
+            "bemenu",
+            &self.style,
+            &[
+                ("background", "--nb"),
+                ("background", "--fb"),
+                ("text", "--nf"),
+                ("text", "--ff"),
+                ("prompt", "--tf"),
+                ("selection", "--hb"),
+                ("selection-text", "--hf"),
+                ("border", "--bdr"),
+            ],
+        );
+        // bemenu prints nothing when escaped
+        Ok(run_dmenu(command, input, self.style.retry)?.filter(|selection| !selection.is_empty()))
+    }
+}
+
+pub struct Tofi {
+    style: Style,
+}
+
+impl Tofi {
+    fn command(&self, prompt: Option<&str>) -> Command {
+        let mut command = Command::new("tofi");
+        if self.style.no_config {
+            command.arg("--config").arg("/dev/null");
+        }
+        if let Some(prompt) = prompt {
+            command.arg("--prompt-text").arg(prompt);
+        }
+        if let Some(lines) = self.style.lines {
+            command.arg("--num-results").arg(lines.to_string());
+        }
+        if let Some(font) = &self.style.font {
+            command.arg("--font").arg(font);
+        }
+        match self.style.match_mode.as_deref() {
+            Some("fuzzy") => {
+                command.arg("--fuzzy-match").arg("true");
+            }
+            Some("exact") => {
+                command.arg("--fuzzy-match").arg("false");
+            }
+            _ => {}
+        }
+        if self.style.auto_select {
+            command.arg("--auto-accept-single").arg("true");
+        }
+        command
+    }
+}
+
+impl Picker for Tofi {
+    fn pick(&self, input: &[String], prompt: Option<&str>) -> Result<Option<String>, Error> {
+        run_tofi(self.command(prompt), input, self.style.retry)
+    }
+
+    fn prompt(&self, prompt: &str, placeholder: Option<&str>) -> Result<Option<String>, Error> {
+        let mut command = self.command(Some(prompt));
+        if let Some(placeholder) = placeholder {
+            command.arg("--placeholder-text").arg(placeholder);
+        }
+        if self.style.hidden {
+            command.arg("--hide-input").arg("true");
+        }
+        run_tofi(command, &[], self.style.retry)
+    }
+}
+
+fn run_tofi(command: Command, input: &[String], retry: bool) -> Result<Option<String>, Error> {
+    // some tofi versions omit the trailing newline, others add one
+    Ok(run_dmenu(command, input, retry)?.map(|selection| selection.trim_end().to_string()))
+}
+
+pub struct Dmenu {
+    style: Style,
+}
+
+impl Picker for Dmenu {
+    fn pick(&self, input: &[String], prompt: Option<&str>) -> Result<Option<String>, Error> {
+        let mut command = Command::new("dmenu");
+        if let Some(prompt) = prompt {
+            command.arg("-p").arg(prompt);
+        }
+        if let Some(lines) = self.style.lines {
+            command.arg("-l").arg(lines.to_string());
+        }
+        if let Some(font) = &self.style.font {
+            command.arg("-fn").arg(font);
+        }
+        color_args(
+            &mut command,
+            "dmenu",
+            &self.style,
+            &[("background", "-nb"), ("text", "-nf"), ("selection", "-sb"), ("selection-text", "-sf")],
+        );
+        run_dmenu(command, input, self.style.retry)
+    }
+}
+
+/// Runs fzf inside a new terminal window.
+///
+/// The terminal command is split on whitespace and must accept `-e <command> <args>...` and stay
+/// in the foreground until the command exits, which is the default for foot, alacritty and kitty;
+/// gnome-terminal for example needs `--wait`. Do not use `--hold`, the window would stay open
+/// after the selection. As the terminal does not pass stdin/stdout through, the entries, the
+/// selection and fzf's exit status are exchanged via files in `$XDG_RUNTIME_DIR`.
+pub struct Fzf {
+    terminal: String,
+    no_config: bool,
+    null_input: bool,
+    /// Match the query as substrings instead of fuzzily.
+    exact: bool,
+    /// Query fzf starts with.
+    initial: Option<String>,
+}
+
+impl Picker for Fzf {
+    fn null_input(&self) -> bool {
+        self.null_input
+    }
+
+    fn pick(&self, input: &[String], prompt: Option<&str>) -> Result<Option<String>, Error> {
+        let dir = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(std::path::PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir);
+        let base = dir.join(format!("niri-action-fzf-{}", std::process::id()));
+        let files = ["in", "out", "status"].map(|ext| base.with_extension(ext));
+        let (separator, mut options) = match self.null_input {
+            true => ("\0", vec!["--read0", "--print0"]),
+            false => ("\n", vec![]),
+        };
+        options.push("--print-query");
+        if self.exact {
+            options.push("--exact");
+        }
+        if let Some(initial) = &self.initial {
+            options.extend(["--query", initial.as_str()]);
+        }
+        std::fs::write(&files[0], input.join(separator))?;
+
+        let mut terminal = self.terminal.split_whitespace();
+        let program = terminal.next().ok_or_else(|| format_err!("Empty terminal command"))?;
+        let mut command = Command::new(program);
+        if self.no_config {
+            command.env_remove("FZF_DEFAULT_OPTS").env_remove("FZF_DEFAULT_OPTS_FILE");
+        }
+        let status = command
+            .args(terminal)
+            .arg("-e")
+            .args(["sh", "-c", r#"i=$1 o=$2 s=$3; shift 3; fzf --prompt "$0" "$@" < "$i" > "$o"; echo $? > "$s""#])
+            .arg(prompt.unwrap_or("> "))
+            .args(&files)
+            .args(&options)
+            .status()
+            .map_err(|e| spawn_error(program, e));
+
+        let result = status.and_then(|_| {
+            let code = std::fs::read_to_string(&files[2])?;
+            let output = std::fs::read_to_string(&files[1])?;
+            Ok((code, output))
+        });
+        for file in &files {
+            let _ = std::fs::remove_file(file);
+        }
+        let (code, output) = result?;
+        // 0: an entry was chosen, 1: nothing matched the query, 130: cancelled
+        let mut lines = output.split(separator);
+        let query = lines.next().unwrap_or("").to_string();
+        match code.trim() {
+            "0" => Ok(lines.next().map(|selection| selection.to_string())),
+            "1" => Ok(Some(query)),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Runs a dmenu style program which reads lines on stdin and prints the selection on stdout.
+///
+/// A failed run which still printed something means the picker died while writing the selection,
+/// e.g. when it was killed. The partial output is never used; with `retry` the picker is started
+/// once more, otherwise it counts as a cancel.
+fn run_dmenu(mut command: Command, input: &[String], retry: bool) -> Result<Option<String>, Error> {
+    let program = command.get_program().to_string_lossy().to_string();
+    command.stdin(Stdio::piped()).stdout(Stdio::piped());
+    let mut attempts = if retry { 2 } else { 1 };
+    let output = loop {
+        let mut child = command.spawn().map_err(|e| spawn_error(&program, e))?;
+        {
+            let stdin = child.stdin.as_mut().expect("failed to get stdin");
+            stdin.write_all(input.join("\n").as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+        attempts -= 1;
+        if output.status.success() || output.stdout.is_empty() || attempts == 0 {
+            break output;
+        }
+        crate::warn(&format!("{program} failed ({}) while printing a selection, starting it again", output.status));
+    };
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let selection = String::from_utf8(output.stdout)?;
+    Ok(Some(selection.strip_suffix('\n').unwrap_or(&selection).to_string()))
+}
+
+fn spawn_error(program: &str, err: std::io::Error) -> Error {
+    match err.kind() {
+        std::io::ErrorKind::NotFound => NiriIPCError::PickerNotFound { picker: program.to_string() }.into(),
+        _ => format_err!("Can't open {}: {}", program, err),
+    }
+}